@@ -13,42 +13,50 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use std::cell::Cell;
-
 use gdk::EventKey;
-use notty::{Command, KeyPress, KeyRelease};
-use notty::datatypes::Key;
+use notty::{Command, KeyPress, KeyRelease, ScrollView};
+use notty::datatypes::{Direction, Key};
 
 pub trait FromEvent {
-    fn from_event(&EventKey, &Cell<usize>) -> Option<Box<Command>>;
+    fn from_event(&EventKey) -> Option<Box<Command>>;
 }
 
 impl FromEvent for KeyPress {
-    fn from_event(key: &EventKey, scroll: &Cell<usize>) -> Option<Box<Command>> {
-        if super_mode(key) {
-            if key.keyval == 0xff52 {
-                scroll.set(scroll.get() + 5);
-                return None;
-            } else if key.keyval == 0xff54 {
-                scroll.set(scroll.get().saturating_sub(5));
-                return None;
-            }
+    fn from_event(key: &EventKey) -> Option<Box<Command>> {
+        if let Some(scroll) = scroll_view(key) {
+            return Some(Box::new(scroll) as Box<Command>);
         }
-        scroll.set(0);
         Some(Box::new(KeyPress(keyval(key.keyval))) as Box<Command>)
     }
 }
 
 impl FromEvent for KeyRelease {
-    fn from_event(key: &EventKey, scroll: &Cell<usize>) -> Option<Box<Command>> {
-        if super_mode(key) && (key.keyval == 0xff52 || key.keyval == 0xff54) {
+    fn from_event(key: &EventKey) -> Option<Box<Command>> {
+        if scroll_view(key).is_some() {
             return None;
         }
-        scroll.set(0);
         Some(Box::new(KeyRelease(keyval(key.keyval))) as Box<Command>)
     }
 }
 
+// Super+Up/Down scrolls the view by lines, Shift+PageUp/PageDown by pages, and Shift+Home/End
+// to the top and bottom of the scrollback.
+fn scroll_view(key: &EventKey) -> Option<ScrollView> {
+    match (shift_mode(key), super_mode(key), key.keyval) {
+        (_, true, 0xff52)   => Some(ScrollView::Lines(Direction::Up, 5)),
+        (_, true, 0xff54)   => Some(ScrollView::Lines(Direction::Down, 5)),
+        (true, _, 0xff55)   => Some(ScrollView::Pages(Direction::Up, 1)),
+        (true, _, 0xff56)   => Some(ScrollView::Pages(Direction::Down, 1)),
+        (true, _, 0xff50)   => Some(ScrollView::ToTop),
+        (true, _, 0xff57)   => Some(ScrollView::ToBottom),
+        _                   => None,
+    }
+}
+
+fn shift_mode(key: &EventKey) -> bool {
+    key.state.bits() & 0o1 == 0o1
+}
+
 fn super_mode(key: &EventKey) -> bool {
     key.state.bits() & 0o100 == 0o100
 }
@@ -69,12 +77,26 @@ fn keyval(n: u32) -> Key {
         0xff55          => Key::PageUp,
         0xff56          => Key::PageDown,
         0xff57          => Key::End,
+        0xff63          => Key::Insert,
+        0xff7f          => Key::NumLock,
+        0xff8d          => Key::Keypad('\n'),
+        0xffaa          => Key::Keypad('*'),
+        0xffab          => Key::Keypad('+'),
+        0xffac          => Key::Keypad(','),
+        0xffad          => Key::Keypad('-'),
+        0xffae          => Key::Keypad('.'),
+        0xffaf          => Key::Keypad('/'),
+        b @ 0xffb0...0xffb9 => Key::Keypad((b'0' + (b - 0xffb0) as u8) as char),
+        0xffbd          => Key::Keypad('='),
+        f @ 0xffbe...0xffd5 => Key::Function((f - 0xffbe + 1) as u8),
         0xffe1          => Key::ShiftLeft,
         0xffe2          => Key::ShiftRight,
         0xffe3          => Key::CtrlLeft,
         0xffe4          => Key::CtrlRight,
         0xffe5          => Key::CapsLock,
-        0xffe7 | 0xffeb => Key::Meta,
+        0xffe7          => Key::Meta,
+        0xffeb | 0xffec => Key::Super,
+        0xffed | 0xffee => Key::Hyper,
         0xffe8 | 0xff67 => Key::Menu,
         0xffe9          => Key::AltLeft,
         0xffea          => Key::AltGr,
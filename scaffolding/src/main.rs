@@ -21,15 +21,15 @@ extern crate cairo;
 extern crate tty;
 extern crate notty;
 
-use std::cell::{Cell, RefCell};
+use std::cell::RefCell;
 use std::env;
 use std::io::BufReader;
 use std::sync::mpsc;
 use std::rc::Rc;
 use std::thread;
 
-use notty::{Output, Command, KeyPress, KeyRelease};
-use notty::terminal::Terminal;
+use notty::{Output, Command, Focus, KeyPress, KeyRelease};
+use notty::terminal::{NoEvents, Terminal};
 use gtk::{WidgetTrait, WidgetSignals, ContainerTrait};
 
 mod key;
@@ -60,6 +60,7 @@ fn main() {
     // Handler program output (tty -> screen) on separate thread.
     let (tx_out, rx) = mpsc::channel();
     let (tx_key_press, tx_key_release) = (tx_out.clone(), tx_out.clone());
+    let (tx_focus_in, tx_focus_out) = (tx_out.clone(), tx_out.clone());
     thread::spawn(move || {
         let output = Output::new(BufReader::new(tty_r));
         for cmd in output {
@@ -67,27 +68,21 @@ fn main() {
         }
     });
 
-    // Create the scroll position tracker.
-    let scroll   = Rc::new(Cell::new(0));
-    let (scroll2, scroll3) = (scroll.clone(), scroll.clone());
-
     // Set up logical terminal and renderer.
-    let terminal   = Rc::new(RefCell::new(Terminal::new(COLS, ROWS, tty_w)));
-    let renderer = ScreenRenderer::new(terminal.clone(), scroll.clone(), handle);
+    let terminal   = Rc::new(RefCell::new(Terminal::new(COLS, ROWS, tty_w, NoEvents)));
+    let renderer = ScreenRenderer::new(terminal.clone(), handle);
 
-    // Process screen logic every 50 miliseconds.
+    // Process screen logic every 50 miliseconds, redrawing only if the view has been damaged.
+    // Damage builds up while the program is in the middle of a synchronized update. The whole
+    // view is redrawn, not only the damaged rows.
     let canvas2 = canvas.clone();
     gdk::glib::timeout_add(50, move || {
         use std::sync::mpsc::TryRecvError::*;
 
         let mut terminal = terminal.borrow_mut();
-        let mut redraw = false;
         loop {
             match rx.try_recv() {
-                Ok(cmd)             => {
-                    redraw = true;
-                    cmd.apply(&mut terminal).unwrap();
-                }
+                Ok(cmd)             => cmd.apply(&mut terminal).unwrap(),
                 Err(Disconnected)   => {
                     gtk::main_quit();
                     panic!();
@@ -95,7 +90,9 @@ fn main() {
                 Err(Empty)          => break,
             }
         }
-        if redraw { canvas2.queue_draw(); }
+        if !terminal.update_in_progress() && !terminal.take_damage().is_empty() {
+            canvas2.queue_draw();
+        }
         gdk::glib::Continue(true)
     });
 
@@ -117,7 +114,7 @@ fn main() {
 
     // Connect signal to receive key presses.
     window.connect_key_press_event(move |window, event| {
-        if let Some(cmd) = KeyPress::from_event(event, &*scroll2) {
+        if let Some(cmd) = KeyPress::from_event(event) {
             tx_key_press.send(cmd).unwrap();
         } else { window.queue_draw(); }
         gtk::signal::Inhibit(false)
@@ -125,12 +122,22 @@ fn main() {
 
     // Connect signal to receive key releases.
     window.connect_key_release_event(move |window, event| {
-        if let Some(cmd) = KeyRelease::from_event(event, &*scroll3) {
+        if let Some(cmd) = KeyRelease::from_event(event) {
             tx_key_release.send(cmd).unwrap();
         } else { window.queue_draw(); }
         gtk::signal::Inhibit(false)
     });
 
+    // Connect signals to track window focus.
+    window.connect_focus_in_event(move |_, _| {
+        tx_focus_in.send(Box::new(Focus(true)) as Box<Command>).unwrap();
+        gtk::signal::Inhibit(false)
+    });
+    window.connect_focus_out_event(move |_, _| {
+        tx_focus_out.send(Box::new(Focus(false)) as Box<Command>).unwrap();
+        gtk::signal::Inhibit(false)
+    });
+
     // Show the window and run the GTK event loop.
     window.show_all();
     gtk::main();
@@ -13,7 +13,7 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use std::cell::{Cell, RefCell};
+use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -38,14 +38,13 @@ pub struct ScreenRenderer {
 
 impl ScreenRenderer {
 
-    pub fn new(logic: Rc<RefCell<Terminal>>, scroll: Rc<Cell<usize>>, tty: Arc<Handle>)
-            -> ScreenRenderer {
+    pub fn new(logic: Rc<RefCell<Terminal>>, tty: Arc<Handle>) -> ScreenRenderer {
 
         let len = logic.borrow().width as usize * logic.borrow().height as usize;
         let Color(r,g,b) = cfg::DEFAULT_BG;
         ScreenRenderer {
             logic: logic,
-            text: RefCell::new(TextRenderer::new(len, scroll)),
+            text: RefCell::new(TextRenderer::new(len)),
             red: r as f64 / 255.0,
             green: g as f64 / 255.0,
             blue: b as f64 / 255.0,
@@ -73,18 +72,18 @@ impl ScreenRenderer {
 
         // Render the text
         let logic = self.logic.borrow();
-        let offset = (logic.grid_height.saturating_sub(logic.height)) * logic.grid_width;
-        let coords = logic.cursor_position();
-        if let Some(info) = logic.tooltip_at(coords) {
+        if let Some(info) = logic.tooltip_at(logic.cursor_position()) {
             println!("{}", info);
         }
-        let cursor_pos = ((coords.y * logic.grid_width) + coords.x).saturating_sub(offset);
-        self.text.borrow_mut().draw(logic.into_iter(),
-                                    cursor_pos as usize,
+        let region = logic.view_region();
+        let width = region.right - region.left;
+        let cursor_pos = logic.cursor_in_view().map(|c| (c.y * width + c.x) as usize);
+        self.text.borrow_mut().draw(logic.view(),
+                                    cursor_pos,
                                     logic.cursor_styles(),
+                                    logic.focused(),
                                     &canvas,
-                                    logic.grid_width as usize,
-                                    offset as usize);
+                                    width as usize);
 
     }
 
@@ -13,9 +13,7 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use std::cell::Cell;
 use std::ops::Range;
-use std::rc::Rc;
 
 use notty::cfg;
 use notty::datatypes::Color;
@@ -28,7 +26,6 @@ use pangocairo::wrap::{PangoAttribute, PangoAttrList, PangoLayout};
 
 pub struct TextRenderer {
     text: String,
-    scroll: Rc<Cell<usize>>,
     fg_color: Vec<(Range<usize>, Color)>,
     bg_color: Vec<(Range<usize>, Color)>,
     opacity: Vec<(Range<usize>, u8)>,
@@ -42,10 +39,9 @@ pub struct TextRenderer {
 
 impl TextRenderer {
 
-    pub fn new(len: usize, scroll: Rc<Cell<usize>>) -> TextRenderer {
+    pub fn new(len: usize) -> TextRenderer {
         TextRenderer {
             text: String::with_capacity(len),
-            scroll: scroll,
             fg_color: Vec::with_capacity(len),
             bg_color: Vec::with_capacity(len),
             opacity: Vec::with_capacity(len),
@@ -60,11 +56,11 @@ impl TextRenderer {
 
     pub fn draw<'a, Cells>(&mut self,
                            cells: Cells,
-                           cursor_pos: usize,
+                           cursor_pos: Option<usize>,
                            cursor_style: Styles,
+                           focused: bool,
                            canvas: &Context,
-                           width: usize,
-                           offset: usize)
+                           width: usize)
     where Cells: Iterator<Item=&'a CharCell> {
 
         // Line positioning
@@ -75,12 +71,9 @@ impl TextRenderer {
         let cast = |x| x as f64 / 255.0;
         canvas.set_source_rgb(cast(r), cast(g), cast(b));
 
-        // Determine offset
-        let offset = offset.saturating_sub(self.scroll.get() * width as usize);
-        
         // Create the styles and text string
-        for (n, cell) in cells.skip(offset).enumerate() {
-            if n != 0 && n % (width as usize) == 0 {
+        for (n, cell) in cells.enumerate() {
+            if n >= width && n % width == 0 {
                 self.text.push('\n');
                 self.extend_style();
             }
@@ -94,8 +87,10 @@ impl TextRenderer {
             }
             let range = lower..self.text.len();
             self.add_style(&range, cell.style());
-            if n == cursor_pos {
+            if Some(n) == cursor_pos && focused {
                 self.cursor_style(&range, &cursor_style);
+            } else if Some(n) == cursor_pos {
+                append_bool(range.clone(), &mut self.underline);
             }
         }
 
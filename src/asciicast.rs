@@ -0,0 +1,340 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Recording and replaying terminal sessions in the asciicast v2 format.
+//!
+//! A recording begins with a header line giving the size of the terminal, followed by one line
+//! for each event: `[time, "o", data]` for output of the controlling process and `[time, "r",
+//! "COLSxROWS"]` for a resize, where `time` is in seconds from the start of the recording.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{self, Value};
+
+use output::{Output, Queue};
+use terminal::Terminal;
+
+/// Writes the output of a session and its resizes as asciicast v2.
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+    // Bytes at the end of the output which do not yet form a whole code point.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Start a recording of a terminal of this size, writing its header.
+    pub fn new(mut out: W, width: u32, height: u32, title: Option<&str>)
+            -> io::Result<Recorder<W>> {
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since)   => since.as_secs(),
+            Err(_)      => 0,
+        };
+        let title = match title {
+            Some(title) => format!(", \"title\": {}", try!(json(&title))),
+            None        => String::new(),
+        };
+        try!(writeln!(out, "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}{}}}",
+                      width, height, timestamp, title));
+        Ok(Recorder {
+            out: out,
+            start: Instant::now(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Record output of the controlling process. A code point split between calls is recorded
+    /// once it is complete, and bytes which are not UTF-8 are recorded as U+FFFD.
+    pub fn output(&mut self, data: &[u8]) -> io::Result<()> {
+        self.pending.extend(data.iter().cloned());
+        let text = take_utf8(&mut self.pending);
+        if text.is_empty() { return Ok(()); }
+        self.event("o", &text)
+    }
+
+    pub fn resize(&mut self, cols: u32, rows: u32) -> io::Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+
+    /// End the recording, returning what it was written to.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        if !self.pending.is_empty() {
+            let text = String::from_utf8_lossy(&self.pending).into_owned();
+            try!(self.event("o", &text));
+        }
+        try!(self.out.flush());
+        Ok(self.out)
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        let time = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        let line = try!(json(&(time, kind, data)));
+        writeln!(self.out, "{}", line)
+    }
+}
+
+/// Wraps the tty read by `Output`, recording everything read from it. The recorder is shared so
+/// that resizes can be recorded from elsewhere.
+pub struct Recorded<R: Read, W: Write> {
+    tty: R,
+    recorder: Arc<Mutex<Recorder<W>>>,
+}
+
+impl<R: Read, W: Write> Recorded<R, W> {
+    pub fn new(tty: R, recorder: Arc<Mutex<Recorder<W>>>) -> Recorded<R, W> {
+        Recorded { tty: tty, recorder: recorder }
+    }
+}
+
+impl<R: Read, W: Write> Read for Recorded<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.tty.read(buf));
+        if n > 0 {
+            let mut recorder = match self.recorder.lock() {
+                Ok(recorder)    => recorder,
+                Err(poisoned)   => poisoned.into_inner(),
+            };
+            try!(recorder.output(&buf[..n]));
+        }
+        Ok(n)
+    }
+}
+
+/// An event of a recording.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Output(String),
+    Input(String),
+    Resize(u32, u32),
+    Marker(String),
+}
+
+/// Reads a recording as its events, each with its time in seconds from the start.
+pub struct Replay<R: BufRead> {
+    lines: io::Lines<R>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<R: BufRead> Replay<R> {
+    /// Read the header of a recording.
+    pub fn new(recording: R) -> io::Result<Replay<R>> {
+        let mut lines = recording.lines();
+        let header = match lines.next() {
+            Some(line)  => try!(parse(&try!(line))),
+            None        => return Err(invalid("empty recording")),
+        };
+        if header.find("version").and_then(Value::as_u64) != Some(2) {
+            return Err(invalid("not an asciicast v2 recording"));
+        }
+        let size = |key: &str| header.find(key).and_then(Value::as_u64).map(|n| n as u32);
+        match (size("width"), size("height")) {
+            (Some(width), Some(height)) => {
+                Ok(Replay { lines: lines, width: width, height: height })
+            }
+            _                           => Err(invalid("recording has no size")),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Replay<R> {
+    type Item = io::Result<(f64, Event)>;
+
+    fn next(&mut self) -> Option<io::Result<(f64, Event)>> {
+        loop {
+            let line = match self.lines.next() {
+                Some(Ok(line))  => line,
+                Some(Err(err))  => return Some(Err(err)),
+                None            => return None,
+            };
+            if line.trim().is_empty() { continue; }
+            return Some(parse(&line).and_then(|value| event(&value)));
+        }
+    }
+}
+
+/// Drive a terminal from a recording. Between events, this waits for the time which passed
+/// between them in the recording divided by `speed`; an infinite speed replays without waiting.
+/// Input events and markers are skipped.
+pub fn replay<R: BufRead>(recording: R, terminal: &mut Terminal, speed: f64) -> io::Result<()> {
+    let events = try!(Replay::new(recording));
+    terminal.set_visible_width(events.width);
+    terminal.set_visible_height(events.height);
+    let queue = Queue::default();
+    let mut output = Output::new(BufReader::new(queue.clone()));
+    let start = Instant::now();
+    for event in events {
+        let (time, event) = try!(event);
+        let target = time / speed;
+        if target.is_finite() && target > 0.0 {
+            let target = Duration::new(target.trunc() as u64, (target.fract() * 1e9) as u32);
+            let elapsed = start.elapsed();
+            if target > elapsed { thread::sleep(target - elapsed); }
+        }
+        match event {
+            Event::Output(data)         => {
+                queue.push(data.as_bytes());
+                // The parser keeps a partial escape sequence until the next output completes
+                // it.
+                while let Some(cmd) = output.next() {
+                    try!(try!(cmd).apply(terminal));
+                }
+            }
+            Event::Resize(cols, rows)   => {
+                terminal.set_visible_width(cols);
+                terminal.set_visible_height(rows);
+            }
+            Event::Input(_) | Event::Marker(_)  => (),
+        }
+    }
+    Ok(())
+}
+
+fn event(value: &Value) -> io::Result<(f64, Event)> {
+    let fields = match value.as_array() {
+        Some(fields) if fields.len() == 3   => fields,
+        _                                   => return Err(invalid("malformed event")),
+    };
+    let (time, kind, data) = match (fields[0].as_f64(), fields[1].as_string(),
+                                    fields[2].as_string()) {
+        (Some(time), Some(kind), Some(data))    => (time, kind, data),
+        _                                       => return Err(invalid("malformed event")),
+    };
+    let event = match kind {
+        "o" => Event::Output(String::from(data)),
+        "i" => Event::Input(String::from(data)),
+        "m" => Event::Marker(String::from(data)),
+        "r" => {
+            let mut size = data.splitn(2, 'x').map(|n| n.parse::<u32>().ok());
+            match (size.next(), size.next()) {
+                (Some(Some(cols)), Some(Some(rows)))    => Event::Resize(cols, rows),
+                _                                       => return Err(invalid("malformed resize")),
+            }
+        }
+        _   => return Err(invalid("unknown event")),
+    };
+    Ok((time, event))
+}
+
+// Take the longest prefix of the bytes which is UTF-8, replacing invalid bytes with U+FFFD but
+// leaving an incomplete code point at the end for later bytes to complete.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = match str::from_utf8(&bytes[start..]) {
+            Ok(_)       => bytes.len(),
+            Err(err)    => start + err.valid_up_to(),
+        };
+        text.push_str(&String::from_utf8_lossy(&bytes[start..end]));
+        if end == bytes.len() {
+            start = end;
+            break;
+        }
+        let rest = &bytes[end..];
+        let width = match rest[0] {
+            0xc0...0xdf => 2,
+            0xe0...0xef => 3,
+            0xf0...0xf7 => 4,
+            _           => 0,
+        };
+        if rest.len() < width && rest[1..].iter().all(|&b| b & 0xc0 == 0x80) {
+            start = end;
+            break;
+        }
+        text.push('\u{fffd}');
+        start = end + 1;
+    }
+    bytes.drain(..start);
+    text
+}
+
+fn json<T: ::serde::Serialize>(value: &T) -> io::Result<String> {
+    serde_json::to_string(value).map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+}
+
+fn parse(line: &str) -> io::Result<Value> {
+    serde_json::from_str(line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::INFINITY;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    use headless::Headless;
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        let recorder = Recorder::new(Vec::new(), 5, 2, Some("demo")).unwrap();
+        let recorder = Arc::new(Mutex::new(recorder));
+        let mut tty = Recorded::new(&b"ab\x1b[1mc\xc3"[..], recorder.clone());
+        let mut buf = Vec::new();
+        tty.read_to_end(&mut buf).unwrap();
+        {
+            let mut recorder = recorder.lock().unwrap();
+            recorder.resize(6, 2).unwrap();
+            recorder.output(b"\xa9\xff").unwrap();
+        }
+        drop(tty);
+        let recording = match Arc::try_unwrap(recorder) {
+            Ok(recorder)    => recorder.into_inner().unwrap().into_inner().unwrap(),
+            Err(_)          => panic!("recorder still shared"),
+        };
+
+        let events = Replay::new(&recording[..]).unwrap();
+        assert_eq!((events.width, events.height), (5, 2));
+        let events: Vec<Event> = events.map(|event| event.unwrap().1).collect();
+        assert_eq!(events, vec![Event::Output(String::from("ab\x1b[1mc")), Event::Resize(6, 2),
+                                Event::Output(String::from("\u{e9}\u{fffd}"))]);
+
+        let mut headless = Headless::new(5, 2);
+        replay(&recording[..], headless.terminal_mut(), INFINITY).unwrap();
+        assert_eq!(headless.snapshot(), "|abc\u{e9}\u{fffd} |\n|      |\n\
+                                         cursor at row 0 col 5\n\
+                                         style at row 0 cols 2-4: bold\n");
+    }
+
+    #[test]
+    fn split_escape() {
+        let recording = "{\"version\": 2, \"width\": 4, \"height\": 1}\n\
+                         [0.0, \"o\", \"a\\u001b[\"]\n\
+                         [0.1, \"i\", \"x\"]\n\
+                         [0.2, \"o\", \"1mb\"]\n";
+        let mut headless = Headless::new(4, 1);
+        replay(recording.as_bytes(), headless.terminal_mut(), INFINITY).unwrap();
+        assert_eq!(headless.snapshot(), "|ab  |\ncursor at row 0 col 2\n\
+                                         style at row 0 cols 1-1: bold\n");
+    }
+
+    #[test]
+    fn invalid_recordings() {
+        assert!(Replay::new(&b""[..]).is_err());
+        assert!(Replay::new(&b"{\"version\": 1, \"width\": 4, \"height\": 1}\n"[..]).is_err());
+        let mut events = Replay::new(&b"{\"version\": 2, \"width\": 4, \"height\": 1}\n\
+                                        [0.0, \"r\", \"4by1\"]\n"[..]).unwrap();
+        assert!(events.next().unwrap().is_err());
+    }
+}
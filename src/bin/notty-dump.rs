@@ -0,0 +1,205 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Decode the output of a program into the commands notty parses from it.
+//!
+//! `notty-dump [--summary] [FILE]` reads raw output, or an asciicast v2 recording, from FILE or
+//! from standard input. It prints a line for each command with the range of bytes it was parsed
+//! from, those bytes and the command's `repr`, marking with `!` the codes notty does not
+//! support and the output it could not parse, and with `?` bytes it ignored. It ends with a
+//! summary of the features the output used.
+extern crate notty;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::process;
+
+use notty::Output;
+use notty::asciicast::{Event, Replay};
+
+#[derive(Default)]
+struct Summary {
+    commands: u64,
+    features: HashMap<String, u64>,
+    unsupported: HashMap<String, u64>,
+    malformed: HashMap<String, u64>,
+    ignored: u64,
+    ignored_bytes: u64,
+}
+
+fn main() {
+    let mut summary_only = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--summary"                 => summary_only = true,
+            "-h" | "--help"             => {
+                println!("usage: notty-dump [--summary] [FILE]");
+                return;
+            }
+            _ if path.is_none()         => path = Some(arg.clone()),
+            _                           => fail("usage: notty-dump [--summary] [FILE]"),
+        }
+    }
+    let data = match read(path) {
+        Ok(data)    => data,
+        Err(err)    => fail(&err.to_string()),
+    };
+    if let Err(err) = dump(&data, summary_only) {
+        fail(&err.to_string());
+    }
+}
+
+fn read(path: Option<String>) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    match path {
+        Some(path)  => try!(try!(File::open(path)).read_to_end(&mut data)),
+        None        => try!(io::stdin().read_to_end(&mut data)),
+    };
+    // A recording is decoded to the output it recorded.
+    if data.starts_with(b"{") {
+        if let Ok(events) = Replay::new(&data[..]) {
+            let mut output = Vec::new();
+            for event in events {
+                if let (_, Event::Output(text)) = try!(event) {
+                    output.extend(text.bytes());
+                }
+            }
+            return Ok(output);
+        }
+    }
+    Ok(data)
+}
+
+fn dump(data: &[u8], summary_only: bool) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut summary = Summary::default();
+    let mut end = 0;
+    let mut output = Output::new(BufReader::new(data));
+    loop {
+        let cmd = match output.next() {
+            Some(cmd)   => try!(cmd),
+            None        => break,
+        };
+        let (start, next) = output.span();
+        if start > end {
+            try!(summary.ignore(&mut out, data, end, start, summary_only));
+        }
+        end = next;
+        let bytes = &data[start as usize..end as usize];
+        let repr = cmd.repr();
+        let unsupported = summary.command(bytes, &repr);
+        if !summary_only {
+            let mark = if unsupported { "!" } else { " " };
+            try!(writeln!(out, "{} {:>8}-{:<8} {:<24} {}", mark, start, end, escape(bytes), repr));
+        }
+    }
+    if (data.len() as u64) > end {
+        try!(summary.ignore(&mut out, data, end, data.len() as u64, summary_only));
+    }
+    summary.write(&mut out, data.len())
+}
+
+impl Summary {
+    // Count a command, returning whether it includes a code notty does not support or output
+    // it could not parse.
+    fn command(&mut self, bytes: &[u8], repr: &str) -> bool {
+        self.commands += 1;
+        if bytes.first().map_or(false, |&b| b >= 0x20 && b != 0x7f) {
+            *self.features.entry(String::from("TEXT")).or_insert(0) += 1;
+            return false;
+        }
+        let mut unsupported = false;
+        let reprs = if repr.starts_with("SERIES: ") { &repr[8..] } else { repr };
+        for repr in reprs.split("; ") {
+            if repr.starts_with("NO FEATURE: ") {
+                *self.unsupported.entry(String::from(&repr[12..])).or_insert(0) += 1;
+                unsupported = true;
+            } else if repr.starts_with("MALFORMED: ") || repr.starts_with("RECOVERED: ") {
+                *self.malformed.entry(String::from(repr)).or_insert(0) += 1;
+                unsupported = true;
+            } else {
+                *self.features.entry(feature(repr)).or_insert(0) += 1;
+            }
+        }
+        unsupported
+    }
+
+    fn ignore<W: Write>(&mut self, out: &mut W, data: &[u8], start: u64, end: u64,
+                        summary_only: bool) -> io::Result<()> {
+        self.ignored += 1;
+        self.ignored_bytes += end - start;
+        if summary_only { return Ok(()); }
+        let bytes = &data[start as usize..end as usize];
+        writeln!(out, "? {:>8}-{:<8} {:<24} IGNORED", start, end, escape(bytes))
+    }
+
+    fn write<W: Write>(&self, out: &mut W, bytes: usize) -> io::Result<()> {
+        try!(writeln!(out, "\n{} commands from {} bytes", self.commands, bytes));
+        try!(write_counts(out, "features used", &self.features));
+        try!(write_counts(out, "unsupported codes", &self.unsupported));
+        try!(write_counts(out, "malformed output", &self.malformed));
+        if self.ignored > 0 {
+            try!(writeln!(out, "\nignored {} sequences ({} bytes)", self.ignored,
+                          self.ignored_bytes));
+        }
+        Ok(())
+    }
+}
+
+fn write_counts<W: Write>(out: &mut W, title: &str, counts: &HashMap<String, u64>)
+        -> io::Result<()> {
+    if counts.is_empty() { return Ok(()); }
+    let mut counts: Vec<(&String, &u64)> = counts.iter().collect();
+    counts.sort_by(|a, b| (b.1, a.0).cmp(&(a.1, b.0)));
+    try!(writeln!(out, "\n{}:", title));
+    for (name, count) in counts {
+        try!(writeln!(out, "{:>8}  {}", count, name));
+    }
+    Ok(())
+}
+
+// The name of the feature a command uses, which is its repr without its numeric arguments.
+fn feature(repr: &str) -> String {
+    let words: Vec<&str> = repr.split(' ').filter(|word| {
+        !word.starts_with(|c: char| c.is_digit(10))
+    }).collect();
+    words.join(" ")
+}
+
+// Write bytes with control characters in caret notation, as in "^[[1m".
+fn escape(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for c in String::from_utf8_lossy(bytes).chars() {
+        match c {
+            '\x00'...'\x1f'         => {
+                out.push('^');
+                out.push((c as u8 + 0x40) as char);
+            }
+            '\x7f'                  => out.push_str("^?"),
+            '\u{80}'...'\u{9f}'     => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            _                       => out.push(c),
+        }
+    }
+    out
+}
+
+fn fail(msg: &str) -> ! {
+    let _ = writeln!(io::stderr(), "notty-dump: {}", msg);
+    process::exit(1);
+}
@@ -23,10 +23,21 @@ pub static FONT: &'static str = "Inconsolatazi4 10";
 
 pub static LOGFILE: &'static str = "~/.log/notty";
 
+// How many problems in the output are logged each second.
+pub static DIAGNOSTIC_LIMIT: u32 = 20;
+
 // SCOLLBACK
 
 pub static SCROLLBACK: u32 = 512;
 
+// HORIZONTAL SCROLLING
+
+pub static MAX_WIDTH: u32 = 1024;
+
+// SELECTION
+
+pub static WORD_SEPARATORS: &'static str = " \t()[]{}<>'\"`,;:|";
+
 // TABS
 
 pub static TAB_STOP: u32 = 4;
@@ -16,6 +16,7 @@
 use notty_encoding::cmds::{Erase, RemoveChars, RemoveRows, InsertBlank, InsertRows};
 
 use command::prelude::*;
+use datatypes::Area;
 
 impl Command for Erase {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
@@ -72,3 +73,29 @@ impl Command for InsertRows {
         }
     }
 }
+
+/// Erase the rows which have scrolled above the visible screen (ED 3).
+pub struct EraseScrollback;
+
+impl Command for EraseScrollback {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.erase_scrollback();
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("ERASE SCROLLBACK")
+    }
+}
+
+/// Erase the characters in an area which are not protected (DECSED and DECSEL).
+pub struct SelectiveErase(pub Area);
+
+impl Command for SelectiveErase {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.selective_erase(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SELECTIVE ERASE")
+    }
+}
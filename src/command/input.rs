@@ -14,7 +14,7 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use command::prelude::*;
-use datatypes::Key;
+use datatypes::{Direction, Key};
 
 pub struct KeyPress(pub Key);
 
@@ -37,3 +37,40 @@ impl Command for KeyRelease {
         String::from("KEY RELEASE")
     }
 }
+
+pub struct Focus(pub bool);
+
+impl Command for Focus {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_focused(self.0)
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("FOCUS IN"),
+            false   => String::from("FOCUS OUT"),
+        }
+    }
+}
+
+/// Move the view of the active grid through its scrollback.
+pub enum ScrollView {
+    Lines(Direction, u32),
+    Pages(Direction, u32),
+    ToTop,
+    ToBottom,
+}
+
+impl Command for ScrollView {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        match *self {
+            ScrollView::Lines(dir, n)   => terminal.scroll_view(dir, n),
+            ScrollView::Pages(dir, n)   => terminal.scroll_view_pages(dir, n),
+            ScrollView::ToTop           => terminal.scroll_view_to_top(),
+            ScrollView::ToBottom        => terminal.scroll_view_to_bottom(),
+        }
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SCROLL VIEW")
+    }
+}
@@ -57,6 +57,61 @@ impl Command for SetTitle {
     }
 }
 
+/// Report the current working directory of the controlling process.
+pub struct SetCwd(pub String);
+
+impl Command for SetCwd {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_cwd(self.0.clone());
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SET CWD")
+    }
+}
+
+/// Begin a hyperlink to a URI, or end the current hyperlink if `None`.
+pub struct SetHyperlink(pub Option<String>);
+
+impl Command for SetHyperlink {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_hyperlink(self.0.clone());
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            Some(_) => String::from("BEGIN HYPERLINK"),
+            None    => String::from("END HYPERLINK"),
+        }
+    }
+}
+
+/// Copy text to the clipboard.
+pub struct SetClipboard(pub String);
+
+impl Command for SetClipboard {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_clipboard(&self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SET CLIPBOARD")
+    }
+}
+
+/// Ask for the terminal to be resized to a number of columns and rows.
+pub struct RequestResize(pub u32, pub u32);
+
+impl Command for RequestResize {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.request_resize(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("REQUEST RESIZE {}x{}", self.0, self.1)
+    }
+}
+
 impl Command for SetInputMode {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
         terminal.set_input_mode(self.0);
@@ -71,6 +126,117 @@ impl Command for SetInputMode {
     }
 }
 
+/// Switch to the notty input mode, with the flags which control which keys are reported.
+pub struct SetNottyInputMode(pub u32);
+
+impl Command for SetNottyInputMode {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_input_flags(self.0);
+        terminal.set_input_mode(InputMode::Notty(()));
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET INPUTMODE EXTENDED {:#x}", self.0)
+    }
+}
+
+pub struct PushKeyboardFlags(pub u32);
+
+impl Command for PushKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.push_keyboard_flags(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("PUSH KEYBOARD FLAGS {}", self.0)
+    }
+}
+
+pub struct PopKeyboardFlags(pub u32);
+
+impl Command for PopKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.pop_keyboard_flags(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("POP {} KEYBOARD FLAGS", self.0)
+    }
+}
+
+pub struct SetKeyboardFlags(pub u32, pub u32);
+
+impl Command for SetKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_keyboard_flags(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET KEYBOARD FLAGS {} MODE {}", self.0, self.1)
+    }
+}
+
+pub struct SetModifyOtherKeys(pub u32);
+
+impl Command for SetModifyOtherKeys {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_modify_other_keys(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET MODIFY OTHER KEYS {}", self.0)
+    }
+}
+
+/// Enable or disable reporting focus changes to the controlling process (mode 1004).
+pub struct SetFocusReporting(pub bool);
+
+impl Command for SetFocusReporting {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_focus_reporting(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("SET FOCUS REPORTING ON"),
+            false   => String::from("SET FOCUS REPORTING OFF"),
+        }
+    }
+}
+
+/// Begin or end a synchronized update (mode 2026), during which the screen should not be
+/// redrawn.
+pub struct SetSynchronizedUpdate(pub bool);
+
+impl Command for SetSynchronizedUpdate {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_synchronized_update(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("BEGIN SYNCHRONIZED UPDATE"),
+            false   => String::from("END SYNCHRONIZED UPDATE"),
+        }
+    }
+}
+
+/// Switch the numeric keypad between application (DECKPAM) and numeric (DECKPNM) mode.
+pub struct SetKeypadMode(pub bool);
+
+impl Command for SetKeypadMode {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_keypad_mode(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("SET KEYPAD MODE APPLICATION"),
+            false   => String::from("SET KEYPAD MODE NUMERIC"),
+        }
+    }
+}
+
 #[derive(Default, Copy, Clone)]
 pub struct Bell;
 
@@ -13,10 +13,8 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use std::fs::File;
-use std::io::Write;
-
 use command::prelude::*;
+use terminal::Problem;
 
 mod echo;
 mod erase;
@@ -38,10 +36,16 @@ pub use notty_encoding::cmds::{
     SetStyleInArea, DefaultStyleInArea,
 };
 
-pub use self::input::{KeyPress, KeyRelease};
-pub use self::meta::{SetTitle, Bell};
+pub use self::erase::{EraseScrollback, SelectiveErase};
+pub use self::input::{KeyPress, KeyRelease, Focus, ScrollView};
+pub use self::meta::{SetTitle, Bell, SetNottyInputMode};
+pub use self::meta::{SetCwd, SetHyperlink, SetClipboard, RequestResize};
+pub use self::meta::{PushKeyboardFlags, PopKeyboardFlags, SetKeyboardFlags, SetModifyOtherKeys};
+pub use self::meta::{SetFocusReporting, SetKeypadMode, SetSynchronizedUpdate};
 pub use self::put::{Put, PutAt};
-pub use self::respond::{StaticResponse, ReportPosition};
+pub use self::respond::{StaticResponse, ReportPosition, ReportKeyboardFlags};
+pub use self::respond::ReportPrivateMode;
+pub use self::style::SetProtected;
 pub use self::tooltip::{AddToolTip, RemoveToolTip, AddDropDown};
 
 mod prelude {
@@ -80,13 +84,37 @@ impl Command for CommandSeries {
 pub struct NoFeature(pub String);
 
 impl Command for NoFeature {
-    fn apply(&self, _: &mut Terminal) -> io::Result<()> {
-        if let Ok(mut file) = File::open(::cfg::LOGFILE) {
-            let _ = write!(file, "{}", self.repr());
-        }
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.report(Problem::Unsupported, &self.0);
         Ok(())
     }
     fn repr(&self) -> String {
         format!("NO FEATURE: {}", self.0)
     }
 }
+
+/// Output which could not be parsed, and was dropped.
+pub struct Malformed(pub String);
+
+impl Command for Malformed {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.report(Problem::Malformed, &self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("MALFORMED: {}", self.0)
+    }
+}
+
+/// A sequence which was abandoned when an unexpected byte interrupted it.
+pub struct Recovered(pub String);
+
+impl Command for Recovered {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.report(Problem::Recovered, &self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("RECOVERED: {}", self.0)
+    }
+}
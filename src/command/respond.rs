@@ -17,6 +17,7 @@ use std::borrow::Cow;
 
 use command::prelude::*;
 use datatypes::{Key, Coords, Code};
+use datatypes::InputMode::Ansi;
 
 pub struct StaticResponse(pub &'static str);
 
@@ -35,8 +36,8 @@ impl Command for ReportPosition {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
         let Coords { x, y } = terminal.cursor_position();
         let cmd = match self.0 {
-            Code::ANSI  => Cow::Owned(format!("\x1b[{};{}R", y, x)),
-            _           => unimplemented!(),
+            Code::ANSI  => Cow::Owned(format!("\x1b[{};{}R", y + 1, x + 1)),
+            _           => return Ok(()),
         };
         terminal.send_input(Key::Cmd(cmd), true)
     }
@@ -44,3 +45,48 @@ impl Command for ReportPosition {
         String::from("REPORT POSITION")
     }
 }
+
+pub struct ReportKeyboardFlags;
+
+impl Command for ReportKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let cmd = Cow::Owned(format!("\x1b[?{}u", terminal.keyboard_flags()));
+        terminal.send_input(Key::Cmd(cmd), true)
+    }
+    fn repr(&self) -> String {
+        String::from("REPORT KEYBOARD FLAGS")
+    }
+}
+
+/// Respond to DECRQM for a DEC private mode. Modes the terminal tracks are reported as set (1)
+/// or reset (2), modes it recognizes but does not implement as permanently reset (4), and any
+/// other mode as not recognized (0).
+pub struct ReportPrivateMode(pub u32);
+
+impl Command for ReportPrivateMode {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let set = match self.0 {
+            1       => Some(match terminal.input_mode() { Ansi(app_mode) => app_mode, _ => false }),
+            12      => Some(terminal.cursor_styles().blink),
+            25      => Some(terminal.cursor_styles().opacity != 0),
+            1004    => Some(terminal.focus_reporting()),
+            1049    => Some(terminal.buffer_pushed()),
+            2026    => Some(terminal.synchronized_update()),
+            _       => None,
+        };
+        let state = match set {
+            Some(true)  => 1,
+            Some(false) => 2,
+            None        => match self.0 {
+                6 | 7 | 30 | 41 | 47 | 66 | 69 | 1000...1007 | 1034...1037 | 1039...1043 |
+                1047 | 1048 | 1050 | 2004  => 4,
+                _                           => 0,
+            }
+        };
+        let cmd = Cow::Owned(format!("\x1b[?{};{}$y", self.0, state));
+        terminal.send_input(Key::Cmd(cmd), true)
+    }
+    fn repr(&self) -> String {
+        format!("REPORT PRIVATE MODE {}", self.0)
+    }
+}
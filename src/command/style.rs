@@ -65,3 +65,19 @@ impl Command for DefaultStyleInArea {
         String::from("DEFAULT STYLE IN AREA")
     }
 }
+
+/// Mark subsequently written characters as protected from selective erasure (DECSCA).
+pub struct SetProtected(pub bool);
+
+impl Command for SetProtected {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_protected(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("SET PROTECTED ON"),
+            false   => String::from("SET PROTECTED OFF"),
+        }
+    }
+}
@@ -13,6 +13,7 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
 use std::mem;
 
 use datatypes::{Area, Coords, Direction, Region, move_within};
@@ -32,6 +33,25 @@ pub struct CoordsIter {
 impl CoordsIter {
 
     pub fn from_area(area: Area, cursor: Coords, screen: Region) -> CoordsIter {
+        // Areas given by the output may reach beyond the screen; only the part within it is
+        // iterated over.
+        let area = match area {
+            CursorBound(coords)     => CursorBound(Coords {
+                x: cmp::min(coords.x, screen.right - 1),
+                y: cmp::min(coords.y, screen.bottom - 1),
+            }),
+            Bound(region)           => Bound(Region {
+                left: cmp::max(region.left, screen.left),
+                top: cmp::max(region.top, screen.top),
+                right: cmp::min(region.right, screen.right),
+                bottom: cmp::min(region.bottom, screen.bottom),
+            }),
+            Rows(top, bottom)       => Rows(cmp::max(top, screen.top),
+                                            cmp::min(bottom, screen.bottom)),
+            Columns(left, right)    => Columns(cmp::max(left, screen.left),
+                                               cmp::min(right, screen.right)),
+            area                    => area,
+        };
         match area {
             CursorCell              => CoordsIter {
                 point: cursor,
@@ -71,14 +91,14 @@ impl CoordsIter {
             Bound(region)           => CoordsIter::from_region(region),
             Rows(top, bottom)       => CoordsIter {
                 point: Coords {x: screen.left, y: top },
-                back_point: Coords {x: screen.right - 1, y: bottom - 1},
+                back_point: Coords {x: screen.right - 1, y: bottom.saturating_sub(1)},
                 region: screen,
                 dir: Right,
                 fin: !(top < bottom),
             },
             Columns(left, right)    => CoordsIter {
                 point: Coords {x: left, y: screen.top},
-                back_point: Coords {x: right - 1, y: screen.bottom - 1},
+                back_point: Coords {x: right.saturating_sub(1), y: screen.bottom - 1},
                 region: screen,
                 dir: Down,
                 fin: !(left < right),
@@ -104,10 +124,13 @@ impl CoordsIter {
     pub fn from_region(region: Region) -> CoordsIter {
         CoordsIter {
             point: Coords {x: region.left, y: region.top},
-            back_point: Coords {x: region.right-1, y: region.bottom-1},
+            back_point: Coords {
+                x: region.right.saturating_sub(1),
+                y: region.bottom.saturating_sub(1),
+            },
             region: region,
             dir: Right,
-            fin: false,
+            fin: !(region.left < region.right && region.top < region.bottom),
         }
     }
 
@@ -156,7 +179,7 @@ impl DoubleEndedIterator for CoordsIter {
 impl ExactSizeIterator for CoordsIter {
 
     fn len(&self) -> usize {
-
+        if self.fin { return 0 }
         match self.dir {
             Up if self.point.x == self.back_point.x     => {
                 (self.point.y - self.back_point.y + 1) as usize
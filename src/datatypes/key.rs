@@ -36,6 +36,8 @@ pub enum Key {
     AltLeft,
     AltGr,
     Meta,
+    Super,
+    Hyper,
     Menu,
     PageUp,
     PageDown,
@@ -48,6 +50,9 @@ pub enum Key {
     ScrollLock,
     /// A function; the byte value indicates the key number.
     Function(u8),
+    /// A key on the numeric keypad; the char is the one printed on the key with num lock on. The
+    /// keypad enter key is `Keypad('\n')`.
+    Keypad(char),
     /// This key is not generated by the keyboard but as a response to some escape code sent
     /// to the output.
     Cmd(Cow<'static, str>),
@@ -59,7 +64,8 @@ pub enum Key {
 impl Key {
     pub fn is_modifier(&self) -> bool {
         match *self {
-            ShiftLeft | ShiftRight | CtrlLeft | CtrlRight | AltLeft | AltGr | CapsLock => true,
+            ShiftLeft | ShiftRight | CtrlLeft | CtrlRight | AltLeft | AltGr | Meta | Super | Hyper
+                | CapsLock  => true,
             _   => false
         }
     }
@@ -82,6 +82,11 @@ pub enum Code {
     Notty,
 }
 
+/// Write a color in the hexadecimal notation of CSS, as `#rrggbb`.
+pub fn hex_color(Color(r, g, b): Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
 /// Calculate the movement from one coordinate to another within a region.
 pub fn move_within(Coords {x, y}: Coords, movement: Movement, region: Region) -> Coords {
     use self::Movement::*;
@@ -96,36 +101,40 @@ pub fn move_within(Coords {x, y}: Coords, movement: Movement, region: Region) ->
         ToEdge(Right)       => Coords {x: region.right - 1, y: y},
         ToBeginning         => Coords {x: region.left, y: region.top},
         ToEnd               => Coords {x: region.right - 1, y: region.bottom - 1},
-        To(Up, n, true) if region.top + n > y       => {
-            let x = x.saturating_sub((region.top + n - y) / (region.bottom - region.top) + 1);
-            let y = region.bottom - (region.top + n - y) % (region.bottom - region.top);
+        To(Up, n, true) if region.top.saturating_add(n) > y     => {
+            let over = region.top.saturating_add(n) - y;
+            let x = x.saturating_sub(over / (region.bottom - region.top) + 1);
+            let y = region.bottom - over % (region.bottom - region.top);
             if x < region.left {
                 Coords { x: region.left, y: region.top }
             } else {
                 Coords { x: x, y: y }
             }
         }
-        To(Down, n, true) if y + n >= region.bottom  => {
-            let x = x + (y + n - region.bottom) / (region.bottom - region.top) + 1;
-            let y = region.top + (y + n - region.bottom) % (region.bottom - region.top);
+        To(Down, n, true) if y.saturating_add(n) >= region.bottom   => {
+            let over = y.saturating_add(n) - region.bottom;
+            let x = x.saturating_add(over / (region.bottom - region.top) + 1);
+            let y = region.top + over % (region.bottom - region.top);
             if x >= region.right {
                 Coords { x: region.right - 1, y: region.bottom - 1 }
             } else {
                 Coords { x: x, y: y }
             }
         }
-        To(Left, n, true) if region.left + n > x    => {
-            let y = y.saturating_sub((region.left + n - x) / (region.right - region.left) + 1);
-            let x = region.right - (region.left + n - x) % (region.right - region.left);
+        To(Left, n, true) if region.left.saturating_add(n) > x  => {
+            let over = region.left.saturating_add(n) - x;
+            let y = y.saturating_sub(over / (region.right - region.left) + 1);
+            let x = region.right - over % (region.right - region.left);
             if y < region.top {
                 Coords { x: region.left, y: region.top }
             } else {
                 Coords { x: x, y: y }
             }
         }
-        To(Right, n, true) if x + n >= region.right  => {
-            let y = y + (x + n - region.right) / (region.right - region.left) + 1;
-            let x = region.left + (x + n - region.right) % (region.right - region.left);
+        To(Right, n, true) if x.saturating_add(n) >= region.right   => {
+            let over = x.saturating_add(n) - region.right;
+            let y = y.saturating_add(over / (region.right - region.left) + 1);
+            let x = region.left + over % (region.right - region.left);
             if y >= region.bottom {
                 Coords { x: region.right - 1, y: region.bottom - 1 }
             } else {
@@ -144,21 +153,17 @@ pub fn move_within(Coords {x, y}: Coords, movement: Movement, region: Region) ->
         To(Right, n, _) | IndexTo(Right, n)   => {
             Coords {x: cmp::min(x.saturating_add(n), region.right - 1), y: y}
         }
-        Tab(Left, n, true) if region.left + n > x => {
-            unimplemented!()
-        }
-        Tab(Right, n, true) if x + n >= region.right => {
-            unimplemented!()
-        }
-        Tab(Left, n, false)                 => {
+        // Tabs stop at the edge of the region, whether or not they may wrap.
+        Tab(Left, n, _)                     => {
             let tab = ((x / cfg::TAB_STOP).saturating_sub(n)) * cfg::TAB_STOP;
             Coords {x: cmp::max(tab, region.left), y: y}
         }
-        Tab(Right, n, false)                => {
-            let tab = ((x / cfg::TAB_STOP) + n) * cfg::TAB_STOP;
+        Tab(Right, n, _)                    => {
+            let tab = (x / cfg::TAB_STOP).saturating_add(n).saturating_mul(cfg::TAB_STOP);
             Coords {x: cmp::min(tab, region.right - 1), y: y}
         }
-        Tab(..)                             => unimplemented!(),
+        // Vertical tabs do not move the cursor.
+        Tab(..)                             => Coords {x: x, y: y},
         PreviousLine(n)                     => {
             Coords {x: 0, y: cmp::max(y.saturating_sub(n), region.top)}
         }
@@ -0,0 +1,232 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! Scenarios in the manner of vttest, each comparing the screen a program's output leaves with
+//! what a VT100-compatible terminal shows.
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use datatypes::Direction::Up;
+use datatypes::{InputMode, Key};
+use datatypes::args::Argument;
+use terminal::{TerminalEvents, SYNC_TIMEOUT_MS};
+
+use super::Headless;
+
+fn screen(width: u32, height: u32, data: &[u8]) -> String {
+    let mut terminal = Headless::new(width, height);
+    terminal.feed(data).unwrap();
+    terminal.snapshot()
+}
+
+#[test]
+fn autowrap() {
+    assert_eq!(screen(5, 3, b"abcdefg"),
+               "|abcde|\n|fg   |\n|     |\ncursor at row 1 col 2\n");
+}
+
+#[test]
+fn carriage_return_and_newline() {
+    assert_eq!(screen(5, 3, b"ab\r\ncd"),
+               "|ab   |\n|cd   |\n|     |\ncursor at row 1 col 2\n");
+}
+
+#[test]
+fn backspace() {
+    assert_eq!(screen(5, 3, b"ab\x08c"),
+               "|ac   |\n|     |\n|     |\ncursor at row 0 col 2\n");
+}
+
+#[test]
+fn scroll_at_bottom() {
+    assert_eq!(screen(5, 3, b"1\r\n2\r\n3\r\n4"),
+               "|2    |\n|3    |\n|4    |\ncursor at row 2 col 1\n");
+}
+
+#[test]
+fn cursor_position() {
+    assert_eq!(screen(5, 3, b"\x1b[2;3HX"),
+               "|     |\n|  X  |\n|     |\ncursor at row 1 col 3\n");
+}
+
+#[test]
+fn relative_movement() {
+    assert_eq!(screen(5, 3, b"\x1b[3;3H\x1b[A\x1b[2D\x1b[B\x1b[3CX\x1b[10A\x1b[10DY"),
+               "|Y    |\n|     |\n|   X |\ncursor at row 0 col 1\n");
+}
+
+#[test]
+fn erase_in_line() {
+    assert_eq!(screen(5, 3, b"abcde\x1b[1;3H\x1b[K"),
+               "|ab   |\n|     |\n|     |\ncursor at row 0 col 2\n");
+}
+
+#[test]
+fn erase_in_display() {
+    assert_eq!(screen(5, 3, b"abc\r\ndef\x1b[1;2H\x1b[J"),
+               "|a    |\n|     |\n|     |\ncursor at row 0 col 1\n");
+}
+
+#[test]
+fn delete_characters() {
+    assert_eq!(screen(5, 3, b"abcd\x1b[1;2H\x1b[P"),
+               "|acd  |\n|     |\n|     |\ncursor at row 0 col 1\n");
+}
+
+#[test]
+fn insert_lines() {
+    assert_eq!(screen(5, 3, b"a\r\nb\r\nc\x1b[2;1H\x1b[L"),
+               "|a    |\n|     |\n|b    |\ncursor at row 1 col 0\n");
+}
+
+#[test]
+fn delete_lines() {
+    assert_eq!(screen(5, 3, b"a\r\nb\r\nc\x1b[2;1H\x1b[M"),
+               "|a    |\n|c    |\n|     |\ncursor at row 1 col 0\n");
+}
+
+#[test]
+fn text_styles() {
+    assert_eq!(screen(5, 3, b"\x1b[1;4mab\x1b[0mc\x1b[38;2;16;32;48md"),
+               "|abcd |\n|     |\n|     |\ncursor at row 0 col 4\n\
+                style at row 0 cols 0-1: bold underline\n\
+                style at row 0 cols 3-3: fg #102030\n");
+}
+
+#[test]
+fn wide_characters() {
+    assert_eq!(screen(5, 3, "a\u{4e2d}b".as_bytes()),
+               "|a\u{4e2d}b |\n|     |\n|     |\ncursor at row 0 col 4\n");
+}
+
+#[test]
+fn alternate_screen() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"abc\x1b[?1049hxy").unwrap();
+    assert_eq!(terminal.snapshot(), "|xy   |\n|     |\n|     |\ncursor at row 0 col 2\n");
+    terminal.feed(b"\x1b[?1049l").unwrap();
+    assert_eq!(terminal.snapshot(), "|abc  |\n|     |\n|     |\ncursor at row 0 col 3\n");
+}
+
+#[test]
+fn device_status_reports() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"\x1b[2;3H\x1b[6n\x1b[5n").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[2;3R\x1b[0n");
+    assert!(terminal.take_responses().is_empty());
+}
+
+#[test]
+fn sequences_split_between_feeds() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"ab\x1b[").unwrap();
+    terminal.feed(b"2;").unwrap();
+    terminal.feed(b"3Hc\x1b]0;ti").unwrap();
+    terminal.feed(b"tle\x07").unwrap();
+    assert_eq!(terminal.snapshot(), "|ab   |\n|  c  |\n|     |\ncursor at row 1 col 3\n");
+    assert_eq!(terminal.terminal().title(), "title");
+}
+
+#[test]
+fn private_mode_reports() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"\x1b[?1h\x1b[?1$p\x1b[?25$p\x1b[?25l\x1b[?25$p").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[?1;1$y\x1b[?25;1$y\x1b[?25;2$y");
+    terminal.feed(b"\x1b[?1049h\x1b[?1049$p\x1b[?2004$p\x1b[?9999$p").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[?1049;1$y\x1b[?2004;4$y\x1b[?9999;0$y");
+}
+
+#[test]
+fn keyboard_flags_per_screen() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"\x1b[>1u\x1b[?1049h\x1b[?u\x1b[>3u\x1b[?u").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[?0u\x1b[?3u");
+    terminal.feed(b"\x1b[?1049l\x1b[?u").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[?1u");
+}
+
+#[test]
+fn focus_reports() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"\x1b[?1004h").unwrap();
+    terminal.terminal_mut().set_focused(false).unwrap();
+    terminal.terminal_mut().set_focused(false).unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[O");
+    terminal.feed(b"\x1b[?1004l").unwrap();
+    terminal.terminal_mut().set_focused(true).unwrap();
+    terminal.terminal_mut().set_focused(false).unwrap();
+    assert!(terminal.take_responses().is_empty());
+    terminal.feed(b"\x1b[?1004h").unwrap();
+    terminal.terminal_mut().set_input_mode(InputMode::Notty(()));
+    terminal.terminal_mut().set_focused(true).unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b{1;17}");
+}
+
+#[test]
+fn notty_input_flags() {
+    let mut terminal = Headless::new(5, 3);
+    let mode = InputMode::Notty(()).encode();
+    terminal.feed(format!("\x1b{{80;{}}}", mode).as_bytes()).unwrap();
+    terminal.terminal_mut().send_input(Key::CapsLock, true).unwrap();
+    assert!(terminal.take_responses().is_empty());
+    terminal.feed(format!("\x1b{{80;{};1}}", mode).as_bytes()).unwrap();
+    terminal.terminal_mut().send_input(Key::CapsLock, true).unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b{1;12}");
+}
+
+#[test]
+fn synchronized_update() {
+    let mut terminal = Headless::new(5, 3);
+    assert!(!terminal.terminal().update_in_progress());
+    terminal.feed(b"\x1b[?2026h").unwrap();
+    assert!(terminal.terminal().update_in_progress());
+    let timeout = Instant::now() + Duration::from_millis(SYNC_TIMEOUT_MS);
+    assert!(!terminal.terminal().update_in_progress_at(timeout));
+    assert!(terminal.terminal().synchronized_update());
+    terminal.feed(b"\x1b[?2026l").unwrap();
+    assert!(!terminal.terminal().update_in_progress());
+    assert!(!terminal.terminal().synchronized_update());
+}
+
+#[test]
+fn reports_leave_the_view_scrolled_back() {
+    let mut terminal = Headless::new(5, 3);
+    terminal.feed(b"1\r\n2\r\n3\r\n4\r\n5").unwrap();
+    terminal.terminal_mut().scroll_view(Up, 1);
+    terminal.feed(b"\x1b[6n").unwrap();
+    assert_eq!(terminal.take_responses(), b"\x1b[3;2R");
+    assert_eq!(terminal.terminal().view_offset(), 1);
+}
+
+struct Recorder(Rc<RefCell<Vec<String>>>);
+
+impl TerminalEvents for Recorder {
+    fn bell(&mut self) {
+        self.0.borrow_mut().push(String::from("bell"));
+    }
+    fn title_changed(&mut self, title: &str) {
+        self.0.borrow_mut().push(format!("title {}", title));
+    }
+}
+
+#[test]
+fn title_and_bell() {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let mut terminal = Headless::with_events(5, 3, Recorder(events.clone()));
+    terminal.feed(b"\x1b]0;hello\x07\x07").unwrap();
+    assert_eq!(*events.borrow(), ["title hello", "bell"]);
+    assert_eq!(terminal.terminal().title(), "hello");
+}
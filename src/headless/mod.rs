@@ -0,0 +1,175 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! A terminal which runs without a screen, for testing what programs draw.
+//!
+//! `Headless` feeds the output of a program through `Output` into a `Terminal` whose tty is a
+//! buffer in memory, and describes the visible screen as text which tests can compare.
+use std::cell::RefCell;
+use std::io::{self, BufReader, Write};
+use std::mem;
+use std::rc::Rc;
+
+use cfg;
+use datatypes::hex_color;
+use output::{Output, Queue};
+use terminal::{CharCell, NoDiagnostics, NoEvents, Styles, Terminal, TerminalEvents};
+
+#[cfg(test)]
+mod conformance;
+
+pub struct Headless {
+    terminal: Terminal,
+    tty: Rc<RefCell<Vec<u8>>>,
+    queue: Queue,
+    output: Output<BufReader<Queue>>,
+}
+
+impl Headless {
+    pub fn new(width: u32, height: u32) -> Headless {
+        Headless::with_events(width, height, NoEvents)
+    }
+
+    /// Create a headless terminal which passes its side effects to `events`. Problems in the
+    /// output are counted but not logged.
+    pub fn with_events<E: TerminalEvents + 'static>(width: u32, height: u32, events: E)
+            -> Headless {
+        let tty = Rc::new(RefCell::new(Vec::new()));
+        let mut terminal = Terminal::new(width, height, Tty(tty.clone()), events);
+        terminal.diagnostics_mut().set_sink(NoDiagnostics);
+        let queue = Queue::default();
+        Headless {
+            terminal: terminal,
+            tty: tty,
+            output: Output::new(BufReader::new(queue.clone())),
+            queue: queue,
+        }
+    }
+
+    /// Apply output from a program to the terminal. An escape sequence split between calls is
+    /// held until the call which completes it.
+    pub fn feed(&mut self, data: &[u8]) -> io::Result<()> {
+        self.queue.push(data);
+        while let Some(cmd) = self.output.next() {
+            try!(try!(cmd).apply(&mut self.terminal));
+        }
+        Ok(())
+    }
+
+    /// Take everything the terminal has written to its tty, such as responses to queries.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        mem::replace(&mut *self.tty.borrow_mut(), Vec::new())
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+
+    pub fn snapshot(&self) -> String {
+        snapshot(&self.terminal)
+    }
+}
+
+// The tty of a headless terminal, shared with the harness so that it can read what was written.
+struct Tty(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Tty {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend(buf.iter().cloned());
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Describe the visible screen of a terminal as text. Each row of the view is written between
+/// bars, followed by the position of the cursor within the view, then a line for each run of
+/// cells in a row which share a style other than the default:
+///
+/// ```text
+/// |ab   |
+/// |     |
+/// cursor at row 0 col 2
+/// style at row 0 cols 0-1: bold fg #ff0000
+/// ```
+///
+/// The extension cells of wide characters are left out of the rows, and images are written as
+/// U+FFFC.
+pub fn snapshot(terminal: &Terminal) -> String {
+    let region = terminal.view_region();
+    let width = (region.right - region.left) as usize;
+    let cells: Vec<&CharCell> = terminal.view().collect();
+    let mut out = String::new();
+    for row in cells.chunks(width) {
+        out.push('|');
+        for cell in row {
+            match **cell {
+                CharCell::Empty(_)              => out.push(' '),
+                CharCell::Char(c, _)            => out.push(c),
+                CharCell::Grapheme(ref s, _)    => out.push_str(s),
+                CharCell::Image { .. }          => out.push('\u{fffc}'),
+                CharCell::Extension(..)         => (),
+            }
+        }
+        out.push_str("|\n");
+    }
+    match terminal.cursor_in_view() {
+        Some(coords)    => {
+            out.push_str(&format!("cursor at row {} col {}\n", coords.y, coords.x));
+        }
+        None            => out.push_str("cursor hidden\n"),
+    }
+    for (y, row) in cells.chunks(width).enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let style = *row[x].style();
+            let run = row[x..].iter().take_while(|cell| *cell.style() == style).count();
+            if style != Styles::default() {
+                out.push_str(&format!("style at row {} cols {}-{}:{}\n", y, x, x + run - 1,
+                                      describe(&style)));
+            }
+            x += run;
+        }
+    }
+    out
+}
+
+// The ways in which a style differs from the default, each preceded by a space.
+fn describe(style: &Styles) -> String {
+    let mut out = String::new();
+    for &(on, name) in &[(style.bold, "bold"), (style.italic, "italic"),
+                         (style.underline, "underline"),
+                         (style.double_underline, "double-underline"),
+                         (style.strikethrough, "strikethrough"), (style.inverted, "inverted"),
+                         (style.blink, "blink"), (style.protected, "protected")] {
+        if on {
+            out.push(' ');
+            out.push_str(name);
+        }
+    }
+    if style.opacity != 0xff { out.push_str(&format!(" opacity {}", style.opacity)); }
+    if style.fg_color != cfg::DEFAULT_FG {
+        out.push_str(&format!(" fg {}", hex_color(style.fg_color)));
+    }
+    if style.bg_color != cfg::DEFAULT_BG {
+        out.push_str(&format!(" bg {}", hex_color(style.bg_color)));
+    }
+    out
+}
@@ -13,19 +13,25 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-#![feature(str_char, drain)]
+#![feature(str_char, drain, custom_derive, plugin)]
+#![plugin(serde_macros)]
 
 extern crate image;
 extern crate mime;
 extern crate notty_encoding;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
 extern crate unicode_width;
 
+pub mod asciicast;
 pub mod cfg;
 mod command;
 pub mod datatypes;
 mod grapheme_tables;
+pub mod headless;
 mod output;
 pub mod terminal;
 
-pub use command::{Command, KeyPress, KeyRelease};
+pub use command::{Command, KeyPress, KeyRelease, Focus, ScrollView};
 pub use output::Output;
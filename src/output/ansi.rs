@@ -59,27 +59,37 @@ impl AnsiCode {
             (b'D', 0, 0)        => wrap(Move::new(To(Left, self.arg(0,1), false))),
             (b'E', 0, 0)        => wrap(Move::new(NextLine(self.arg(0,1)))),
             (b'F', 0, 0)        => wrap(Move::new(PreviousLine(self.arg(0,1)))),
-            (b'G', 0, 0)        => wrap(Move::new(Column(self.arg(0,1)-1))),
+            (b'G', 0, 0)        => wrap(Move::new(Column(self.arg(0,1).saturating_sub(1)))),
             (b'H', 0, 0)        => wrap(Move::new(Position(Coords {
-                x: self.arg(1,1)-1,
-                y: self.arg(0,1)-1,
+                x: self.arg(1,1).saturating_sub(1),
+                y: self.arg(0,1).saturating_sub(1),
             }))),
             (b'I', 0, 0)        => wrap(Move::new(Tab(Right, self.arg(0,1), false))),
             (b'J', 0, 0)        => match self.arg(0, 0) {
                 0   => wrap(Erase::new(CursorTo(ToEnd))),
                 1   => wrap(Erase::new(CursorTo(ToBeginning))),
                 2   => wrap(Erase::new(WholeScreen)),
-                3   => wrap(NoFeature(self.csi_code())),
+                3   => wrap(EraseScrollback),
+                _   => None
+            },
+            (b'J', b'?', 0)     => match self.arg(0, 0) {
+                0   => wrap(SelectiveErase(CursorTo(ToEnd))),
+                1   => wrap(SelectiveErase(CursorTo(ToBeginning))),
+                2   => wrap(SelectiveErase(WholeScreen)),
                 _   => None
             },
-            (b'J', b'?', 0)     => wrap(NoFeature(self.csi_code())),
             (b'K', 0, 0)        => match self.arg(0, 0) {
                 0   => wrap(Erase::new(CursorTo(ToEdge(Right)))),
                 1   => wrap(Erase::new(CursorTo(ToEdge(Left)))),
                 2   => wrap(Erase::new(CursorRow)),
                 _   => None
             },
-            (b'K', b'?', 0)     => wrap(NoFeature(self.csi_code())),
+            (b'K', b'?', 0)     => match self.arg(0, 0) {
+                0   => wrap(SelectiveErase(CursorTo(ToEdge(Right)))),
+                1   => wrap(SelectiveErase(CursorTo(ToEdge(Left)))),
+                2   => wrap(SelectiveErase(CursorRow)),
+                _   => None
+            },
             (b'L', 0, 0)        => wrap(InsertRows::new(self.arg(0,1), true)),
             (b'M', 0, 0)        => wrap(RemoveRows::new(self.arg(0,1), true)),
             (b'P', 0, 0)        => wrap(RemoveChars::new(self.arg(0,1))),
@@ -88,30 +98,30 @@ impl AnsiCode {
             (b'T', b'>', 0)     => wrap(NoFeature(self.csi_code())),
             (b'X', 0, 0)        => wrap(Erase::new(CursorTo(To(Right, self.arg(0,1), false)))),
             (b'Z', 0, 0)        => wrap(Move::new(Tab(Left, self.arg(0,1), false))),
-            (b'`', 0, 0)        => wrap(Move::new(Column(self.arg(0,1)-1))),
+            (b'`', 0, 0)        => wrap(Move::new(Column(self.arg(0,1).saturating_sub(1)))),
             (b'a', 0, 0)        => wrap(Move::new(To(Right, self.arg(0,1), false))),
             (b'b', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'c', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'c', b'>', 0)     => wrap(NoFeature(self.csi_code())),
-            (b'd', 0, 0)        => wrap(Move::new(Row(self.arg(0,1)-1))),
+            (b'd', 0, 0)        => wrap(Move::new(Row(self.arg(0,1).saturating_sub(1)))),
             (b'e', 0, 0)        => wrap(Move::new(To(Down, self.arg(0,1), false))),
             (b'f', 0, 0)        => wrap(Move::new(Position(Coords {
-                x: self.arg(1,1)-1,
-                y: self.arg(0,1)-1
+                x: self.arg(1,1).saturating_sub(1),
+                y: self.arg(0,1).saturating_sub(1)
             }))),
             (b'g', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'h', 0, 0)        => command_series!(|x| match *x {
                 2   => wrap(NoFeature(self.csi_code())),
                 4   => wrap(NoFeature(self.csi_code())),
                 12  => wrap(NoFeature(self.csi_code())),
-                _   => None,
+                _   => wrap(NoFeature(self.csi_code())),
             }),
             (b'h', b'?', 0)     => command_series!(|x| match *x {
                 1       => wrap(SetInputMode(Ansi(true))),
                 6       => wrap(NoFeature(self.csi_code())),
                 7       => wrap(NoFeature(self.csi_code())),
                 12      => wrap(SetCursorStyle(Blink(true))),
-                25      => wrap(SetCursorStyle(Opacity(0))),
+                25      => wrap(SetCursorStyle(Opacity(0xff))),
                 30      => wrap(NoFeature(self.csi_code())),
                 41      => wrap(NoFeature(self.csi_code())),
                 47      => wrap(NoFeature(self.csi_code())),
@@ -121,7 +131,7 @@ impl AnsiCode {
                 1001    => wrap(NoFeature(self.csi_code())),
                 1002    => wrap(NoFeature(self.csi_code())),
                 1003    => wrap(NoFeature(self.csi_code())),
-                1004    => wrap(NoFeature(self.csi_code())),
+                1004    => wrap(SetFocusReporting(true)),
                 1005    => wrap(NoFeature(self.csi_code())),
                 1006    => wrap(NoFeature(self.csi_code())),
                 1007    => wrap(NoFeature(self.csi_code())),
@@ -139,7 +149,8 @@ impl AnsiCode {
                 1049    => wrap(PushBuffer(false)),
                 1050    => wrap(NoFeature(self.csi_code())),
                 2004    => wrap(NoFeature(self.csi_code())),
-                _       => None
+                2026    => wrap(SetSynchronizedUpdate(true)),
+                _       => wrap(NoFeature(self.csi_code()))
             }),
             (b'i', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'i', b'?', 0)     => wrap(NoFeature(self.csi_code())),
@@ -147,14 +158,14 @@ impl AnsiCode {
                 2   => wrap(NoFeature(self.csi_code())),
                 4   => wrap(NoFeature(self.csi_code())),
                 12  => wrap(NoFeature(self.csi_code())),
-                _   => None,
+                _   => wrap(NoFeature(self.csi_code())),
             }),
             (b'l', b'?', 0)      => command_series!(|x| match *x {
                 1       => wrap(SetInputMode(Ansi(false))),
                 6       => wrap(NoFeature(self.csi_code())),
                 7       => wrap(NoFeature(self.csi_code())),
                 12      => wrap(SetCursorStyle(Blink(false))),
-                25      => wrap(SetCursorStyle(Opacity(0xff))),
+                25      => wrap(SetCursorStyle(Opacity(0))),
                 30      => wrap(NoFeature(self.csi_code())),
                 41      => wrap(NoFeature(self.csi_code())),
                 47      => wrap(NoFeature(self.csi_code())),
@@ -164,7 +175,7 @@ impl AnsiCode {
                 1001    => wrap(NoFeature(self.csi_code())),
                 1002    => wrap(NoFeature(self.csi_code())),
                 1003    => wrap(NoFeature(self.csi_code())),
-                1004    => wrap(NoFeature(self.csi_code())),
+                1004    => wrap(SetFocusReporting(false)),
                 1005    => wrap(NoFeature(self.csi_code())),
                 1006    => wrap(NoFeature(self.csi_code())),
                 1007    => wrap(NoFeature(self.csi_code())),
@@ -182,69 +193,35 @@ impl AnsiCode {
                 1049    => wrap(PopBuffer),
                 1050    => wrap(NoFeature(self.csi_code())),
                 2004    => wrap(NoFeature(self.csi_code())),
-                _       => None
+                2026    => wrap(SetSynchronizedUpdate(false)),
+                _       => wrap(NoFeature(self.csi_code()))
             }),
-            (b'm', 0, 0)        => match self.arg(0, 0) {
-                0               => wrap(DefaultTextStyle),
-                38              => match self.arg(1, 0) {
-                    2   => match (self.arg(3, 257), self.arg(4, 257), self.arg(5, 257)) {
-                        (r, g, b) if r < 256 && g < 256 && b < 256
-                            => wrap(SetTextStyle(FgColor(Color(r as u8, g as u8, b as u8)))),
-                        _   => None
-                    },
-                    5   => wrap(SetTextStyle(FgColorCfg(Some(self.arg(2, 0) as u8)))),
-                    _   => None
-                },
-                48              => match self.arg(1, 0) {
-                    2   => match (self.arg(3, 257), self.arg(4, 257), self.arg(5, 257)) {
-                        (r, g, b) if r < 256 && g < 256 && b < 256
-                            => wrap(SetTextStyle(BgColor(Color(r as u8, g as u8, b as u8)))),
-                        _   => None
-                    },
-                    5   => wrap(SetTextStyle(BgColorCfg(Some(self.arg(2, 0) as u8)))),
-                    _   => None
-                },
-                _               => {
-                    command_series!(|x| match *x {
-                    0               => wrap(DefaultTextStyle),
-                    1               => wrap(SetTextStyle(Bold(true))),
-                    3               => wrap(SetTextStyle(Italic(true))),
-                    4               => wrap(SetTextStyle(Underline(1))),
-                    5 | 6           => wrap(SetTextStyle(Blink(true))),
-                    7               => wrap(SetTextStyle(InvertColors(true))),
-                    8               => wrap(SetTextStyle(Opacity(0))),
-                    9               => wrap(SetTextStyle(Strikethrough(true))),
-                    21              => wrap(SetTextStyle(Underline(2))),
-                    22              => wrap(SetTextStyle(Bold(false))),
-                    23              => wrap(SetTextStyle(Italic(false))),
-                    24              => wrap(SetTextStyle(Underline(0))),
-                    25              => wrap(SetTextStyle(Blink(false))),
-                    27              => wrap(SetTextStyle(InvertColors(false))),
-                    28              => wrap(SetTextStyle(Opacity(0xff))),
-                    29              => wrap(SetTextStyle(Strikethrough(false))),
-                    n @ 30...37     => wrap(SetTextStyle(FgColorCfg(Some((n - 30) as u8)))),
-                    39              => wrap(SetTextStyle(FgColorCfg(None))),
-                    n @ 40...47     => wrap(SetTextStyle(BgColorCfg(Some((n - 40) as u8)))),
-                    49              => wrap(SetTextStyle(BgColorCfg(None))),
-                    n @ 90...97     => wrap(SetTextStyle(FgColorCfg(Some((n - 82) as u8)))),
-                    n @ 100...107   => wrap(SetTextStyle(BgColorCfg(Some((n - 92) as u8)))),
-                    _               => None
-                })
+            (b'm', 0, 0)        => wrap(CommandSeries(sgr(&self.args).into_iter().map(|style| {
+                match style {
+                    Some(style) => Box::new(SetTextStyle(style)) as Box<Command>,
+                    None        => Box::new(DefaultTextStyle) as Box<Command>,
                 }
+            }).collect())),
+            (b'm', b'>', 0)     => match (self.arg(0, 0), self.arg(1, 0)) {
+                (4, n @ 0...2)  => wrap(SetModifyOtherKeys(n)),
+                (4, _)          => None,
+                _               => wrap(NoFeature(self.csi_code())),
             },
-            (b'm', b'>', 0)     => wrap(NoFeature(self.csi_code())),
             (b'n', 0, 0)        => match self.arg(0,5) {
                 5   => wrap(StaticResponse("\x1b[0n")),
                 6   => wrap(ReportPosition(Code::ANSI)),
                 _   => None
             },
-            (b'n', b'>', 0)     => wrap(NoFeature(self.csi_code())),
+            (b'n', b'>', 0)     => match self.arg(0, 0) {
+                4   => wrap(SetModifyOtherKeys(0)),
+                _   => wrap(NoFeature(self.csi_code())),
+            },
             (b'n', b'?', 0)     => wrap(NoFeature(self.csi_code())),
             (b'p', 0, b'!')     => wrap(NoFeature(self.csi_code())),
             (b'p', 0, b'$')     => wrap(NoFeature(self.csi_code())),
             (b'p', 0, b'"')     => wrap(NoFeature(self.csi_code())),
             (b'p', b'>', 0)     => wrap(NoFeature(self.csi_code())),
-            (b'p', b'?', b'$')  => wrap(NoFeature(self.csi_code())),
+            (b'p', b'?', b'$')  => wrap(ReportPrivateMode(self.arg(0,0))),
             (b'q', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'q', 0, b' ')     => match self.arg(0,1) {
                 0 | 1   => wrap(NoFeature(self.csi_code())),
@@ -255,7 +232,11 @@ impl AnsiCode {
                 6       => wrap(NoFeature(self.csi_code())),
                 _       => None,
             },
-            (b'q', 0, b'"')     => wrap(NoFeature(self.csi_code())),
+            (b'q', 0, b'"')     => match self.arg(0, 0) {
+                1       => wrap(SetProtected(true)),
+                0 | 2   => wrap(SetProtected(false)),
+                _       => None,
+            },
             (b'r', 0, 0)        => wrap(NoFeature(self.csi_code())),
             (b'r', 0, b'$')     => {
                 let area = match (self.arg(0,0), self.arg(1,0), self.arg(2,0), self.arg(3,0)) {
@@ -285,11 +266,18 @@ impl AnsiCode {
             (b'r', b'?', 0)     => wrap(NoFeature(self.csi_code())),
             (b's', 0, 0)        => wrap(NoFeature(self.csi_code())), //left and right margins
             (b's', b'?', 0)     => wrap(NoFeature(self.csi_code())),
-            (b't', 0, 0)        => wrap(NoFeature(self.csi_code())), //window manipulation
+            (b't', 0, 0)        => match self.arg(0, 0) { //window manipulation
+                8   => wrap(RequestResize(self.arg(2,0), self.arg(1,0))),
+                _   => wrap(NoFeature(self.csi_code())),
+            },
             (b't', 0, b' ')     => wrap(NoFeature(self.csi_code())),
             (b't', 0, b'$')     => wrap(NoFeature(self.csi_code())), // DECRARA
             (b't', b'>', 0)     => wrap(NoFeature(self.csi_code())),
             (b'u', 0, 0)        => wrap(NoFeature(self.csi_code())), // Restore cursor?
+            (b'u', b'<', 0)     => wrap(PopKeyboardFlags(self.arg(0,1))),
+            (b'u', b'=', 0)     => wrap(SetKeyboardFlags(self.arg(0,0), self.arg(1,1))),
+            (b'u', b'>', 0)     => wrap(PushKeyboardFlags(self.arg(0,0))),
+            (b'u', b'?', 0)     => wrap(ReportKeyboardFlags),
             (b'u', 0, b' ')     => wrap(NoFeature(self.csi_code())),
             (b'v', 0, b'$')     => wrap(NoFeature(self.csi_code())), // Copy an area
             (b'w', 0, b'\'')    => wrap(NoFeature(self.csi_code())),
@@ -304,36 +292,40 @@ impl AnsiCode {
             (b'|', 0, b'\'')    => wrap(NoFeature(self.csi_code())),
             (b'}', 0, b'\'')    => wrap(NoFeature(self.csi_code())), 
             (b'~', 0, b'\'')    => wrap(NoFeature(self.csi_code())), 
-            _                   => None
+            _                   => wrap(NoFeature(self.csi_code()))
         }
     }
 
-    #[allow(unused, dead_code)]
-    pub fn dcs(&self, strarg: &str) -> Option<Box<Command>> {
-        match (self.private_mode, self.preterminal) {
-            (b'|', 0)       => unimplemented!(),
-            (b'$', b'q')    => unimplemented!(),
-            (b'+', b'p')    => unimplemented!(),
-            (b'+', b'q')    => unimplemented!(),
-            _               => unreachable!(),
-        }
+    /// A device control string, identified by the parameters, intermediate bytes and final byte
+    /// which begin it. None are supported.
+    pub fn dcs(&self, header: &str) -> Option<Box<Command>> {
+        wrap(NoFeature(format!("^[P{}", header)))
     }
 
     pub fn osc(&self, strarg: &str) -> Option<Box<Command>> {
         match self.arg(0, 0) {
             0...2   => wrap(SetTitle(RefCell::new(Some(String::from(strarg))))),
-            3   => unimplemented!(),
-            4   => unimplemented!(),
-            5   => unimplemented!(),
-            6   => unimplemented!(),
-            46  => unimplemented!(),
-            50  => unimplemented!(),
-            51  => unimplemented!(),
-            52  => unimplemented!(),
-            104 => unimplemented!(),
-            105 => unimplemented!(),
-            106 => unimplemented!(),
-            _   => None
+            3   => wrap(NoFeature(self.osc_code())),
+            4   => wrap(NoFeature(self.osc_code())),
+            5   => wrap(NoFeature(self.osc_code())),
+            6   => wrap(NoFeature(self.osc_code())),
+            7   => file_path(strarg).and_then(|cwd| wrap(SetCwd(cwd))),
+            8   => wrap(SetHyperlink(strarg.splitn(2, ';').nth(1).and_then(|uri| {
+                if uri.is_empty() { None } else { Some(String::from(uri)) }
+            }))),
+            46  => wrap(NoFeature(self.osc_code())),
+            50  => wrap(NoFeature(self.osc_code())),
+            51  => wrap(NoFeature(self.osc_code())),
+            52  => match strarg.splitn(2, ';').nth(1) {
+                Some("?")   => wrap(NoFeature(format!("{};{}", self.osc_code(), strarg))),
+                Some(data)  => base64(data).and_then(|bytes| String::from_utf8(bytes).ok())
+                                           .and_then(|text| wrap(SetClipboard(text))),
+                None        => None,
+            },
+            104 => wrap(NoFeature(self.osc_code())),
+            105 => wrap(NoFeature(self.osc_code())),
+            106 => wrap(NoFeature(self.osc_code())),
+            _   => wrap(NoFeature(self.osc_code())),
         }
     }
 
@@ -341,14 +333,236 @@ impl AnsiCode {
         self.args.get(idx).map_or(default, |&x|x)
     }
 
+    fn osc_code(&self) -> String {
+        format!("^]{}", self.arg(0, 0))
+    }
+
     fn csi_code(&self) -> String {
         let args = self.args.iter().map(ToString::to_string).collect::<Vec<_>>().join(";");
-        format!("^[[{}{}{}{}", self.private_mode as char, args,
-                self.preterminal as char, self.terminal as char)
+        let mut code = String::from("^[[");
+        if self.private_mode != 0 { code.push(self.private_mode as char); }
+        code.push_str(&args);
+        if self.preterminal != 0 { code.push(self.preterminal as char); }
+        code.push(self.terminal as char);
+        code
+    }
+
+}
+
+/// Interpret the arguments to an SGR code from left to right, as xterm does. Each recognized
+/// parameter produces a style; `None` represents resetting to the default text style.
+fn sgr(args: &[u32]) -> Vec<Option<Style>> {
+    if args.is_empty() { return vec![None]; }
+    let mut styles = Vec::with_capacity(args.len());
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        styles.push(match arg {
+            0               => None,
+            1               => Some(Bold(true)),
+            3               => Some(Italic(true)),
+            4               => Some(Underline(1)),
+            5 | 6           => Some(Blink(true)),
+            7               => Some(InvertColors(true)),
+            8               => Some(Opacity(0)),
+            9               => Some(Strikethrough(true)),
+            21              => Some(Underline(2)),
+            22              => Some(Bold(false)),
+            23              => Some(Italic(false)),
+            24              => Some(Underline(0)),
+            25              => Some(Blink(false)),
+            27              => Some(InvertColors(false)),
+            28              => Some(Opacity(0xff)),
+            29              => Some(Strikethrough(false)),
+            n @ 30...37     => Some(FgColorCfg(Some((n - 30) as u8))),
+            38              => match sgr_color(&mut args) {
+                Some(Ok(color)) => Some(FgColor(color)),
+                Some(Err(n))    => Some(FgColorCfg(Some(n))),
+                None            => continue,
+            },
+            39              => Some(FgColorCfg(None)),
+            n @ 40...47     => Some(BgColorCfg(Some((n - 40) as u8))),
+            48              => match sgr_color(&mut args) {
+                Some(Ok(color)) => Some(BgColor(color)),
+                Some(Err(n))    => Some(BgColorCfg(Some(n))),
+                None            => continue,
+            },
+            49              => Some(BgColorCfg(None)),
+            n @ 90...97     => Some(FgColorCfg(Some((n - 82) as u8))),
+            n @ 100...107   => Some(BgColorCfg(Some((n - 92) as u8))),
+            _               => continue,
+        });
+    }
+    styles
+}
+
+/// Consume the arguments of an extended color (the parameters following 38 or 48). A true color
+/// is returned as `Ok`, an index into the 256 color palette as `Err`. Out of range values are
+/// consumed but ignored.
+fn sgr_color<I: Iterator<Item=u32>>(args: &mut I) -> Option<Result<Color, u8>> {
+    match args.next() {
+        Some(2) => match (args.next(), args.next(), args.next()) {
+            (Some(r), Some(g), Some(b)) if r < 256 && g < 256 && b < 256    => {
+                Some(Ok(Color(r as u8, g as u8, b as u8)))
+            }
+            _                                                               => None
+        },
+        Some(5) => match args.next() {
+            Some(n) if n < 256  => Some(Err(n as u8)),
+            _                   => None
+        },
+        _       => None
     }
+}
 
+// The path of a file URI, as the working directory is reported.
+fn file_path(uri: &str) -> Option<String> {
+    if uri.starts_with("file://") {
+        let rest = &uri[7..];
+        rest.find('/').map(|n| String::from(&rest[n..]))
+    } else if uri.starts_with("/") {
+        Some(String::from(uri))
+    } else { None }
+}
+
+// Decode standard base64, as used to pass text to the clipboard.
+fn base64(data: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in data.bytes().take_while(|&c| c != b'=') {
+        let n = match c {
+            b'A'...b'Z' => c - b'A',
+            b'a'...b'z' => c - b'a' + 26,
+            b'0'...b'9' => c - b'0' + 52,
+            b'+'        => 62,
+            b'/'        => 63,
+            _           => return None,
+        };
+        acc = (acc << 6) | n as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
 }
 
 fn wrap<T: Command>(cmd: T) -> Option<Box<Command>> {
     Some(Box::new(cmd) as Box<Command>)
 }
+
+#[cfg(test)]
+mod tests {
+
+    use cfg;
+    use datatypes::Color;
+    use terminal::Styles;
+
+    use super::{base64, file_path, sgr};
+
+    fn styles(args: &[u32]) -> Styles {
+        sgr(args).into_iter().fold(Styles::default(), |mut styles, style| {
+            match style {
+                Some(style) => styles.update(style),
+                None        => styles = Styles::default(),
+            }
+            styles
+        })
+    }
+
+    #[test]
+    fn sgr_reset() {
+        assert_eq!(styles(&[]), Styles::default());
+        assert_eq!(styles(&[0]), Styles::default());
+        assert_eq!(styles(&[1, 4, 0]), Styles::default());
+        assert_eq!(styles(&[1, 0, 3]), Styles { italic: true, ..Styles::default() });
+    }
+
+    #[test]
+    fn sgr_attributes() {
+        assert_eq!(styles(&[1, 3, 4, 5, 7, 9]), Styles {
+            bold: true,
+            italic: true,
+            underline: true,
+            blink: true,
+            inverted: true,
+            strikethrough: true,
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[4, 21]), Styles { double_underline: true, ..Styles::default() });
+        assert_eq!(styles(&[1, 3, 4, 5, 7, 9, 22, 23, 24, 25, 27, 29]), Styles::default());
+        assert_eq!(styles(&[8]), Styles { opacity: 0, ..Styles::default() });
+        assert_eq!(styles(&[8, 28]), Styles::default());
+    }
+
+    #[test]
+    fn sgr_palette_colors() {
+        assert_eq!(styles(&[31, 42]), Styles {
+            fg_color: cfg::COLORS_256[1],
+            bg_color: cfg::COLORS_256[2],
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[93, 104]), Styles {
+            fg_color: cfg::COLORS_256[11],
+            bg_color: cfg::COLORS_256[12],
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[31, 42, 39, 49]), Styles::default());
+    }
+
+    #[test]
+    fn sgr_extended_colors_with_other_attributes() {
+        assert_eq!(styles(&[1, 38, 5, 196]), Styles {
+            bold: true,
+            fg_color: cfg::COLORS_256[196],
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[38, 2, 255, 0, 0, 48, 5, 4]), Styles {
+            fg_color: Color(255, 0, 0),
+            bg_color: cfg::COLORS_256[4],
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[48, 2, 1, 2, 3, 38, 2, 4, 5, 6, 3]), Styles {
+            fg_color: Color(4, 5, 6),
+            bg_color: Color(1, 2, 3),
+            italic: true,
+            ..Styles::default()
+        });
+        assert_eq!(styles(&[38, 5, 16, 4]), Styles {
+            fg_color: cfg::COLORS_256[16],
+            underline: true,
+            ..Styles::default()
+        });
+    }
+
+    #[test]
+    fn sgr_malformed_extended_colors() {
+        // Out of range values are consumed but ignored.
+        assert_eq!(styles(&[38, 5, 256, 1]), Styles { bold: true, ..Styles::default() });
+        assert_eq!(styles(&[48, 2, 256, 0, 0, 1]), Styles { bold: true, ..Styles::default() });
+        // An unknown color space consumes only its own parameter.
+        assert_eq!(styles(&[38, 7, 1]), Styles { bold: true, ..Styles::default() });
+        // Truncated sequences set nothing.
+        assert_eq!(styles(&[38, 5]), Styles::default());
+        assert_eq!(styles(&[1, 48, 2, 10, 20]), Styles { bold: true, ..Styles::default() });
+        assert_eq!(styles(&[38]), Styles::default());
+    }
+
+    #[test]
+    fn base64_decode() {
+        assert_eq!(base64("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64("aGVsbG8h").unwrap(), b"hello!");
+        assert_eq!(base64("").unwrap(), b"");
+        assert!(base64("aGV*bG8=").is_none());
+    }
+
+    #[test]
+    fn cwd_path() {
+        assert_eq!(file_path("file://host/home/user").unwrap(), "/home/user");
+        assert_eq!(file_path("file:///tmp").unwrap(), "/tmp");
+        assert!(file_path("http://host/tmp").is_none());
+    }
+
+}
@@ -13,8 +13,11 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
+use std::collections::VecDeque;
 use std::io;
 use std::str;
+use std::sync::{Arc, Mutex};
 
 use command::*;
 use datatypes::args::*;
@@ -26,11 +29,20 @@ mod notty;
 use self::ansi::AnsiCode;
 use self::notty::NottyCode;
 
+/// The longest incomplete sequence which will be held while waiting for the rest of it, in bytes.
+/// A longer sequence is abandoned as malformed.
+pub const MAX_SEQUENCE: usize = 1 << 20;
+
 /// The `Output` struct processes data written to the terminal from the controlling process,
-/// parsing it into structured commands. It is implemented as an `Iterator`.
+/// parsing it into structured commands. It is implemented as an `Iterator`, which ends when the
+/// tty reaches the end of its data.
 pub struct Output<R: io::BufRead> {
     tty: R,
     parser: Parser,
+    carry: Vec<u8>,
+    read: u64,
+    start: u64,
+    span: (u64, u64),
 }
 
 impl<R: io::BufRead> Output<R> {
@@ -39,29 +51,95 @@ impl<R: io::BufRead> Output<R> {
         Output {
             tty: tty,
             parser: Parser::default(),
+            carry: Vec::new(),
+            read: 0,
+            start: 0,
+            span: (0, 0),
         }
     }
 
+    /// The range of bytes read from the tty which the last command was parsed from. Bytes
+    /// between the end of one command and the start of the next were ignored.
+    pub fn span(&self) -> (u64, u64) {
+        self.span
+    }
+
+}
+
+/// Output which has been received but not yet parsed, shared between whatever receives it and
+/// the `Output` reading from it. A read takes whatever has been queued, and finds nothing rather
+/// than blocking when the queue is empty.
+#[derive(Clone, Default)]
+pub struct Queue(Arc<Mutex<VecDeque<u8>>>);
+
+impl Queue {
+    pub fn push(&self, data: &[u8]) {
+        self.0.lock().unwrap().extend(data.iter().cloned());
+    }
+}
+
+impl io::Read for Queue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut queue = self.0.lock().unwrap();
+        let n = cmp::min(buf.len(), queue.len());
+        for byte in &mut buf[..n] { *byte = queue.pop_front().unwrap(); }
+        Ok(n)
+    }
 }
 
 impl<R: io::BufRead> Iterator for super::Output<R> {
     type Item = io::Result<Box<Command>>;
 
     fn next(&mut self) -> Option<io::Result<Box<Command>>> {
-        let mut offset = 0;
         loop {
-            match match self.tty.fill_buf() {
-                Ok(buf)     => self.parser.parse(buf, &mut offset),
-                Err(err)    => return Some(Err(err)),
-            } {
+            let mut offset = 0;
+            let ret = if self.carry.is_empty() {
+                let len = match self.tty.fill_buf() {
+                    Ok(buf) if buf.is_empty()   => return None,
+                    Ok(buf)     => {
+                        let ret = self.parser.parse(buf, &mut offset);
+                        // The tty will not be refilled until its buffer is consumed, so the rest
+                        // of an incomplete sequence is carried over to be parsed again.
+                        if ret.is_none() && self.parser.pos.is_some() {
+                            self.carry.extend(buf[offset..].iter().cloned());
+                        }
+                        buf.len()
+                    }
+                    Err(err)    => return Some(Err(err)),
+                };
+                self.tty.consume(if self.carry.is_empty() { offset } else { len });
+                ret
+            } else {
+                if self.parser.pos.is_some() {
+                    let len = match self.tty.fill_buf() {
+                        Ok(buf) if buf.is_empty()   => return None,
+                        Ok(buf)     => { self.carry.extend(buf.iter().cloned()); buf.len() }
+                        Err(err)    => return Some(Err(err)),
+                    };
+                    self.tty.consume(len);
+                    if self.carry.len() > MAX_SEQUENCE {
+                        self.read += self.carry.len() as u64;
+                        self.carry.clear();
+                        self.parser = Parser::default();
+                        self.span = (self.start, self.read);
+                        self.start = self.read;
+                        return wrap(Malformed(format!("sequence longer than {} bytes",
+                                                      MAX_SEQUENCE))).map(Ok);
+                    }
+                }
+                let ret = self.parser.parse(&self.carry, &mut offset);
+                self.carry.drain(..offset);
+                ret
+            };
+            self.read += offset as u64;
+            match ret {
                 Some(cmd)   => {
-                    self.tty.consume(offset);
+                    self.span = (self.start, self.read);
+                    self.start = self.read;
                     return Some(Ok(cmd))
                 }
-                None        => {
-                    self.tty.consume(offset);
-                    offset = 0;
-                }
+                // Unless the parser is partway through a sequence, what it read was ignored.
+                None        => if self.parser.pos.is_none() { self.start = self.read; },
             }
         }
     }
@@ -71,9 +149,11 @@ enum Position {
     Grapheme,
     EscCode,
     CsiCode,
-    #[allow(dead_code)]
     DcsCode,
+    DcsString,
     OscCode,
+    OscString,
+    IgnoreString,
     NottyCode,
     NottyAttach(usize),
 }
@@ -83,6 +163,7 @@ struct Parser {
     cat: Option<gr::GraphemeCat>,
     ansi: AnsiCode,
     notty: NottyCode,
+    dcs: String,
     pos: Option<Position>,
     init: usize,
 }
@@ -90,11 +171,17 @@ struct Parser {
 impl Parser {
     fn parse(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
         match self.pos.take() {
-            Some(Position::Grapheme)    => self.grapheme(buf, offset),
+            Some(Position::Grapheme)    => {
+                self.init = *offset;
+                self.grapheme(buf, offset)
+            }
             Some(Position::EscCode)     => self.esc(buf, offset),
             Some(Position::CsiCode)     => self.csi(buf, offset),
             Some(Position::DcsCode)     => self.dcs(buf, offset),
+            Some(Position::DcsString)   => self.dcs_str(buf, offset),
             Some(Position::OscCode)     => self.osc(buf, offset),
+            Some(Position::OscString)   => self.osc_str(buf, offset),
+            Some(Position::IgnoreString) => self.ignore_str(buf, offset),
             Some(Position::NottyCode)   => self.notty(buf, offset),
             Some(Position::NottyAttach(rem))    => {
                 match self.notty.attachments.append_incomplete(buf, offset, rem) {
@@ -120,8 +207,21 @@ impl Parser {
 
         'grapheme: loop {
             let ch = match code_point(buf, offset) {
-                Some(ch)    => ch,
-                None        => { self.pos = Some(Position::Grapheme); return None }
+                Ok(Some(ch))    => ch,
+                Ok(None)        => {
+                    // The cluster is parsed again from its start once the rest is available.
+                    *offset = self.init;
+                    self.pos = Some(Position::Grapheme);
+                    return None
+                }
+                Err(_) if *offset > self.init + 1 => {
+                    // Put the cluster parsed so far; the invalid byte is reported by the next
+                    // call.
+                    *offset -= 1;
+                    let s = unsafe { str::from_utf8_unchecked(&buf[self.init..*offset]) };
+                    return wrap(Put::new_grapheme(String::from(s)));
+                }
+                Err(b)          => return wrap(Malformed(format!("invalid UTF-8 byte {:#04x}", b))),
             };
             let cat = self.cat.take().unwrap_or_else(|| gr::grapheme_category(ch.char_at(0)));
             state = match (state, cat) {
@@ -171,52 +271,50 @@ impl Parser {
             "\u{90}"    => self.dcs(buf, offset),
             "\u{9b}"    => self.csi(buf, offset),
             "\u{9d}"    => self.osc(buf, offset),
-            "\u{9e}" | "\u{9f}" => {
-                ansi_str(buf, offset);
-                None
-            }
+            "\u{9e}" | "\u{9f}" => self.ignore_str(buf, offset),
             _           => None
         }
     }
 
     fn esc(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
         match byte(buf, *offset) {
-            Some(b' ')  => { ignore(buf, offset, &[b'F', b'G', b'L', b'N']); None }
-            Some(b'#')  => { ignore(buf, offset, &[b'3', b'4', b'5', b'6', b'8']); None }
-            Some(b'%')  => { ignore(buf, offset, &[b'@', b'G']); None }
+            Some(b' ')  => self.ignore(buf, offset, &[b'F', b'G', b'L', b'N']),
+            Some(b'#')  => self.ignore(buf, offset, &[b'3', b'4', b'5', b'6', b'8']),
+            Some(b'%')  => self.ignore(buf, offset, &[b'@', b'G']),
             Some(b'('...b'/') => {
-                ignore(buf, offset, &[b'0', b'<', b'>', b'%', b'A', b'B', b'4', b'C', b'5', b'R',
-                                      b'f', b'Q', b'9', b'K', b'Y', b'`', b'E', b'6', b'Z', b'H',
-                                      b'7', b'=']);
-                None
+                self.ignore(buf, offset, &[b'0', b'<', b'>', b'%', b'A', b'B', b'4', b'C', b'5',
+                                           b'R', b'f', b'Q', b'9', b'K', b'Y', b'`', b'E', b'6',
+                                           b'Z', b'H', b'7', b'='])
             }
-            Some(b'6')  => wrap(NoFeature(String::from("6"))),
-            Some(b'7')  => wrap(NoFeature(String::from("7"))),
-            Some(b'8')  => wrap(NoFeature(String::from("8"))),
-            Some(b'9')  => wrap(NoFeature(String::from("9"))),
-            Some(b'D')  => wrap(NoFeature(String::from("D"))),
+            Some(b'6')  => { *offset += 1; wrap(NoFeature(String::from("6"))) }
+            Some(b'7')  => { *offset += 1; wrap(NoFeature(String::from("7"))) }
+            Some(b'8')  => { *offset += 1; wrap(NoFeature(String::from("8"))) }
+            Some(b'9')  => { *offset += 1; wrap(NoFeature(String::from("9"))) }
+            Some(b'=')  => { *offset += 1; wrap(SetKeypadMode(true)) }
+            Some(b'>')  => { *offset += 1; wrap(SetKeypadMode(false)) }
+            Some(b'D')  => { *offset += 1; wrap(NoFeature(String::from("D"))) }
             Some(b'E')  => { *offset += 1; wrap(Move::new(NextLine(1))) }
-            Some(b'H')  => wrap(NoFeature(String::from("H"))),
-            Some(b'M')  => wrap(NoFeature(String::from("M"))),
+            Some(b'H')  => { *offset += 1; wrap(NoFeature(String::from("H"))) }
+            Some(b'M')  => { *offset += 1; wrap(NoFeature(String::from("M"))) }
             Some(b'P')  => { *offset += 1; self.dcs(buf, offset) }
-            Some(b'Z')  => wrap(NoFeature(String::from("Z"))),
+            Some(b'Z')  => { *offset += 1; wrap(NoFeature(String::from("Z"))) }
             Some(b'[')  => { *offset += 1; self.csi(buf, offset) }
             Some(b']')  => { *offset += 1; self.osc(buf, offset) }
-            Some(b'^') | Some(b'_') => {  ansi_str(buf, offset); None }
-            Some(b'c')  => wrap(NoFeature(String::from("c"))),
+            Some(b'^') | Some(b'_') => { *offset += 1; self.ignore_str(buf, offset) }
+            Some(b'c')  => { *offset += 1; wrap(NoFeature(String::from("c"))) }
             Some(b'N'...b'O')
                 | Some(b'V'...b'X')
                 | Some(b'l'...b'o')
                 | Some(b'|'...b'~') => { *offset += 1; None }
             Some(b'{')  => { *offset += 1; self.notty(buf, offset) }
-            Some(_)     => None,
+            Some(b)     => wrap(Recovered(format!("ESC interrupted by {:#04x}", b))),
             None        => { self.pos = Some(Position::EscCode); None }
         }
     }
 
     fn csi(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
         // These must be kept sorted!!
-        static CSI_PRIVATE_MODES:   &'static [u8]   = &[b'>', b'?'];
+        static CSI_PRIVATE_MODES:   &'static [u8]   = &[b'<', b'=', b'>', b'?'];
         static CSI_PRETERMINALS:    &'static [u8]   = &[b' ', b'!', b'"', b'$', b'\'', b'*'];
         static CSI_TERMINALS:       &'static [u8]   = &[
             b'@', b'A', b'B', b'C', b'D', b'E', b'F', b'G', b'H', b'I', b'J', b'K', b'L', b'M',
@@ -253,7 +351,10 @@ impl Parser {
                     *offset += 1;
                     continue 'csi;
                 }
-                Some(_)     => return None,
+                Some(b)     => {
+                    self.ansi.clear();
+                    return wrap(Recovered(format!("CSI interrupted by {:#04x}", b)));
+                }
                 None        => {
                     self.pos = Some(Position::CsiCode);
                     return None;
@@ -265,9 +366,39 @@ impl Parser {
         ret
     }
 
-    #[allow(unused)]
     fn dcs(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
-        unimplemented!();
+        // The header is kept up to and including its final byte; a string with an overlong or
+        // unterminated header is reported by as much of the header as was kept.
+        'dcs: loop {
+            match byte(buf, *offset) {
+                Some(b @ 0x20...0x3f)   => {
+                    *offset += 1;
+                    if self.dcs.len() < 32 { self.dcs.push(b as char); }
+                }
+                Some(b @ 0x40...0x7e)   => {
+                    *offset += 1;
+                    self.dcs.push(b as char);
+                    break 'dcs;
+                }
+                Some(_)                 => break 'dcs,
+                None                    => {
+                    self.pos = Some(Position::DcsCode);
+                    return None;
+                }
+            }
+        }
+        self.dcs_str(buf, offset)
+    }
+
+    fn dcs_str(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
+        if skip_str(buf, offset) {
+            let ret = self.ansi.dcs(&self.dcs);
+            self.dcs.clear();
+            ret
+        } else {
+            self.pos = Some(Position::DcsString);
+            None
+        }
     }
 
     fn osc(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
@@ -277,38 +408,68 @@ impl Parser {
                     *offset += 1;
                     break 'osc;
                 }
+                // A code without a string, such as ^[]104^G.
+                Some(b'\x07') | Some(b'\x1b') => break 'osc,
                 Some(b'0'...b'9')   => {
-                    ansi_num(buf, offset).map(|n| self.ansi.args.push(n));
+                    match ansi_num(buf, offset) {
+                        Some(n) => self.ansi.args.push(n),
+                        None    => {
+                            self.pos = Some(Position::OscCode);
+                            return None;
+                        }
+                    }
+                }
+                Some(b)             => {
+                    self.ansi.clear();
+                    return wrap(Recovered(format!("OSC interrupted by {:#04x}", b)));
                 }
-                Some(_)             => return None,
                 None                => {
                     self.pos = Some(Position::OscCode);
                     return None;
                 }
             }
         }
+        self.osc_str(buf, offset)
+    }
 
+    fn osc_str(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
         match ansi_str(buf, offset) {
             Some(Some(s))   => {
                 let ret = self.ansi.osc(s);
                 self.ansi.clear();
                 ret
             }
-            Some(None)      => None,
-            None            => { self.pos = Some(Position::OscCode); None }
+            Some(None)      => {
+                self.ansi.clear();
+                wrap(Malformed(String::from("OSC string is not UTF-8")))
+            }
+            None            => { self.pos = Some(Position::OscString); None }
+        }
+    }
+
+    fn ignore(&mut self, buf: &[u8], offset: &mut usize, ignore: &[u8]) -> Option<Box<Command>> {
+        match byte(buf, *offset + 1) {
+            Some(c) if ignore.contains(&c)  => *offset += 2,
+            Some(_)                         => (),
+            None                            => self.pos = Some(Position::EscCode),
         }
+        None
+    }
 
+    fn ignore_str(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
+        if !skip_str(buf, offset) { self.pos = Some(Position::IgnoreString); }
+        None
     }
 
     fn notty(&mut self, buf: &[u8], offset: &mut usize) -> Option<Box<Command>> {
         static ARGCHARS: &'static str = ".0123456789;ABCDEFabcdef";
         'notty: loop {
             match code_point(buf, offset) {
-                Some(s) if ARGCHARS.contains(s) => {
+                Ok(Some(s)) if ARGCHARS.contains(s) => {
                     *offset += 1;
                     self.notty.args.push_str(s);
                 }
-                Some("{")                       => {
+                Ok(Some("{"))                       => {
                     match self.notty.attachments.append(buf, offset) {
                         Some(0) => {
                             continue
@@ -323,20 +484,27 @@ impl Parser {
                         }
                     }
                 }
-                Some("}")                       => {
+                Ok(Some("}"))                       => {
                     *offset += 1;
                     break 'notty;
                 }
-                Some(_)                         => {
-                    return None
+                Ok(Some(s))                         => {
+                    self.notty.clear();
+                    return wrap(Recovered(format!("notty code interrupted by {:?}", s)));
                 }
-                None                            => {
+                Ok(None)                            => {
                     self.pos = Some(Position::NottyCode);
                     return None;
                 }
+                Err(b)                              => {
+                    self.notty.clear();
+                    return wrap(Malformed(format!("invalid UTF-8 byte {:#04x}", b)));
+                }
             }
         }
-        let ret = self.notty.parse();
+        let ret = self.notty.parse().or_else(|| {
+            wrap(Malformed(format!("notty code ^[{{{}}}", self.notty.args)))
+        });
         self.notty.clear();
         ret
     }
@@ -347,64 +515,85 @@ fn byte(buf: &[u8], offset: usize) -> Option<u8> {
     buf.get(offset).map(|&x|x)
 }
 
-fn code_point<'a>(buf: &'a [u8], offset: &mut usize) -> Option<&'a str> {
+// The code point at the offset, or None if the buffer ends first. An invalid byte is skipped,
+// and returned as the error.
+fn code_point<'a>(buf: &'a [u8], offset: &mut usize) -> Result<Option<&'a str>, u8> {
     let width = match byte(buf, *offset) {
         Some(0x00...0x7f)   => 1,
-        Some(0xc3...0xdf)   => 2,
+        Some(0xc2...0xdf)   => 2,
         Some(0xe0...0xef)   => 3,
         Some(0xf0...0xf4)   => 4,
-        Some(_)             => {
+        Some(b)             => {
             *offset += 1;
-            return None;
+            return Err(b);
         }
-        None                => return None,
+        None                => return Ok(None),
     };
+    if buf.len() - *offset < width { return Ok(None) }
     match str::from_utf8(&buf[*offset..(*offset + width)]) {
-        Ok(s)   => Some(s),
+        Ok(s)   => Ok(Some(s)),
         _       => {
             *offset += 1;
-            None
+            Err(buf[*offset - 1])
         }
     }
 }
 
-fn ansi_str<'a>(buf: &'a [u8], offset: &mut usize) -> Option<Option<&'a str>> {
-    let mut offset_tmp = *offset;
+// The end of the contents of a string terminated by BEL or ST, and the offset after its
+// terminator, or None if the buffer ends first. An ESC which does not begin ST ends the string
+// without being consumed.
+fn str_end(buf: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let mut end = offset;
     loop {
-        match byte(buf, offset_tmp) {
-            Some(b'\x07')   => {
-                let ret = str::from_utf8(&buf[*offset..offset_tmp]).ok();
-                *offset = offset_tmp + 1;
-                return Some(ret)
-            }
-            Some(_)         => offset_tmp += 1,
+        match byte(buf, end) {
+            Some(b'\x07')   => return Some((end, end + 1)),
+            Some(b'\x1b')   => return match byte(buf, end + 1) {
+                Some(b'\\')    => Some((end, end + 2)),
+                Some(_)         => Some((end, end)),
+                None            => None,
+            },
+            Some(_)         => end += 1,
             None            => return None,
         }
     }
 }
 
+fn ansi_str<'a>(buf: &'a [u8], offset: &mut usize) -> Option<Option<&'a str>> {
+    str_end(buf, *offset).map(|(end, next)| {
+        let ret = str::from_utf8(&buf[*offset..end]).ok();
+        *offset = next;
+        ret
+    })
+}
+
+// Skip a string terminated by BEL or ST, returning whether it ended. Unlike ansi_str, as much of
+// an incomplete string as possible is consumed, so that it need not be held in memory.
+fn skip_str(buf: &[u8], offset: &mut usize) -> bool {
+    match str_end(buf, *offset) {
+        Some((_, next)) => { *offset = next; true }
+        None            => {
+            *offset = if buf.last() == Some(&b'\x1b') { buf.len() - 1 } else { buf.len() };
+            false
+        }
+    }
+}
+
+// A numeric argument, clamped to 65535, or None without consuming anything if the buffer ends
+// before it does.
 fn ansi_num(buf: &[u8], offset: &mut usize) -> Option<u32> {
     let mut offset_tmp = *offset;
+    let mut n = 0u32;
     loop {
         match byte(buf, offset_tmp) {
-            Some(b'0'...b'9')   => offset_tmp += 1,
-            Some(_)             => {
-                return str::from_utf8(&buf[*offset..offset_tmp]).ok().and_then(|s| {
-                    u32::from_str_radix(s, 10).ok()
-                }).map(|n| { *offset = offset_tmp; n })
+            Some(b @ b'0'...b'9')   => {
+                n = cmp::min(n * 10 + (b - b'0') as u32, 0xffff);
+                offset_tmp += 1;
             }
-            None                => {
-                *offset -= 1;
-                return None
+            Some(_)                 => {
+                *offset = offset_tmp;
+                return Some(n)
             }
-        }
-    }
-}
-
-fn ignore(buf: &[u8], offset: &mut usize, ignore: &[u8]) {
-    if let Some(c) = byte(buf, *offset + 1) {
-        if ignore.contains(&c) {
-            *offset += 2;
+            None                    => return None,
         }
     }
 }
@@ -416,9 +605,13 @@ fn wrap<T: Command>(cmd: T) -> Option<Box<Command>> {
 #[cfg(test)]
 mod tests {
 
+    use std::env;
     use std::io::BufReader;
 
     use command::*;
+    use datatypes::InputMode::{Ansi, Notty};
+    use datatypes::args::Argument;
+    use headless::Headless;
     use super::*;
 
     fn setup(data: &[u8]) -> Output<BufReader<&[u8]>> {
@@ -456,6 +649,66 @@ mod tests {
         assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: SET CURSOR STYLE");
     }
 
+    #[test]
+    fn sgr_code() {
+        let mut output = setup(b"\x1b[1;38;5;196mA\x1b[m");
+        assert_eq!(&output.next().unwrap().unwrap().repr(),
+                   "SERIES: SET TEXT STYLE; SET TEXT STYLE");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: DEFAULT TEXT STYLE");
+    }
+
+    #[test]
+    fn keyboard_flags_code() {
+        let mut output = setup(b"\x1b[>11u\x1b[=2;3u\x1b[?u\x1b[<u");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "PUSH KEYBOARD FLAGS 11");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET KEYBOARD FLAGS 2 MODE 3");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "REPORT KEYBOARD FLAGS");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "POP 1 KEYBOARD FLAGS");
+    }
+
+    #[test]
+    fn modify_other_keys_code() {
+        let mut output = setup(b"\x1b[>4;2m\x1b[>4m\x1b[>4n\x1b[>1;2m");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET MODIFY OTHER KEYS 2");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET MODIFY OTHER KEYS 0");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET MODIFY OTHER KEYS 0");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "NO FEATURE: ^[[>1;2m");
+    }
+
+    #[test]
+    fn focus_reporting_code() {
+        let mut output = setup(b"\x1b[?1004h\x1b[?1004l");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: SET FOCUS REPORTING ON");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: SET FOCUS REPORTING OFF");
+    }
+
+    #[test]
+    fn synchronized_update_code() {
+        let mut output = setup(b"\x1b[?2026h\x1b[?2026$p\x1b[?2026l");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: BEGIN SYNCHRONIZED UPDATE");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "REPORT PRIVATE MODE 2026");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: END SYNCHRONIZED UPDATE");
+    }
+
+    #[test]
+    fn selective_erase_code() {
+        let mut output = setup(b"\x1b[3J\x1b[1\"q\x1b[?2J\x1b[?K\x1b[\"q");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "ERASE SCROLLBACK");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET PROTECTED ON");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SELECTIVE ERASE");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SELECTIVE ERASE");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET PROTECTED OFF");
+    }
+
+    #[test]
+    fn keypad_mode_code() {
+        let mut output = setup(b"\x1b=A\x1b>");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET KEYPAD MODE APPLICATION");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET KEYPAD MODE NUMERIC");
+    }
+
     #[test]
     fn osc_code() {
         let mut output = setup(b"A\x1b]0;Hello, world!\x07B");
@@ -464,6 +717,18 @@ mod tests {
         assert_eq!(&output.next().unwrap().unwrap().repr(), "B");
     }
 
+    #[test]
+    fn side_effect_codes() {
+        let mut output = setup(b"\x1b]8;;http://example.com\x07A\x1b]8;;\x07\x1b]52;c;aGk=\x07");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "BEGIN HYPERLINK");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "END HYPERLINK");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET CLIPBOARD");
+        let mut output = setup(b"\x1b]7;file://host/tmp\x07\x1b[8;24;80t");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET CWD");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "REQUEST RESIZE 80x24");
+    }
+
     #[test]
     fn notty_code() {
         let mut output = setup(b"A\x1b{30;8.ff.ff.ff}\x1b{19;1;2}B");
@@ -473,4 +738,126 @@ mod tests {
         assert_eq!(&output.next().unwrap().unwrap().repr(), "B");
     }
 
+    #[test]
+    fn notty_input_flags() {
+        let data = format!("\x1b{{80;{}}}\x1b{{80;{};3}}\x1b{{80;{};3}}",
+                           Notty(()).encode(), Notty(()).encode(), Ansi(false).encode());
+        let mut output = setup(data.as_bytes());
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET INPUTMODE EXTENDED 0x0");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET INPUTMODE EXTENDED 0x3");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET INPUTMODE ANSI");
+    }
+
+    #[test]
+    fn malformed_output() {
+        let mut output = setup(b"\xffA\x1b[1:B\x1b]x\x1b[5y\x1b[?9999h");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "MALFORMED: invalid UTF-8 byte 0xff");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "RECOVERED: CSI interrupted by 0x3a");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), ":");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "B");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "RECOVERED: OSC interrupted by 0x78");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "x");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "NO FEATURE: ^[[5y");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SERIES: NO FEATURE: ^[[?9999h");
+    }
+
+    #[test]
+    fn malformed_after_cluster() {
+        let mut output = setup(b"\xe1\x84\x80\xffA");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "\u{1100}");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "MALFORMED: invalid UTF-8 byte 0xff");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert!(output.next().is_none());
+    }
+
+    #[test]
+    fn spans() {
+        let mut output = setup(b"A\x1b[2A\x1b(BB");
+        output.next();
+        assert_eq!(output.span(), (0, 1));
+        output.next();
+        assert_eq!(output.span(), (1, 5));
+        output.next();
+        assert_eq!(output.span(), (8, 9));
+    }
+
+    #[test]
+    fn split_reads() {
+        let data = "\x1b[38;5;196mE\u{301}\x1b]0;title\x1b\\\x1b{19;1;2}\u{1101}\u{1161}B";
+        let data = data.as_bytes();
+        let expected: Vec<_> = setup(data).map(|cmd| cmd.unwrap().repr()).collect();
+        assert_eq!(expected.len(), 7);
+        for n in 1..data.len() {
+            let output = Output::new(BufReader::with_capacity(n, data));
+            let cmds: Vec<_> = output.map(|cmd| cmd.unwrap().repr()).collect();
+            assert_eq!(cmds, expected);
+        }
+    }
+
+    #[test]
+    fn string_codes() {
+        let mut output = setup(b"\x1bP1$qm\x1b\\A\x1b_apc\x07B\x1b]2;title\x1b\\\x1b]104\x07C\
+                                 \x1b]0;");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "NO FEATURE: ^[P1$q");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "B");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "SET TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "NO FEATURE: ^]104");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "C");
+        assert!(output.next().is_none());
+    }
+
+    #[test]
+    fn huge_arguments() {
+        let mut output = setup(b"\x1b[99999999999999999999A\x1b[4294967296;4294967296H");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "MOVE UP 65535");
+        assert_eq!(&output.next().unwrap().unwrap().repr(), "MOVE TO 65534,65534");
+    }
+
+    #[test]
+    fn overlong_sequence() {
+        let mut data = b"\x1b]0;".to_vec();
+        data.extend(vec![b'a'; MAX_SEQUENCE]);
+        data.extend(b"\x07A".iter().cloned());
+        let mut output = setup(&data);
+        assert_eq!(&output.next().unwrap().unwrap().repr(),
+                   "MALFORMED: sequence longer than 1048576 bytes");
+        assert_eq!(&output.last().unwrap().unwrap().repr(), "A");
+    }
+
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self) -> u32 {
+            self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+            self.0 >> 16
+        }
+    }
+
+    #[test]
+    fn arbitrary_output() {
+        // Output built mostly from the bytes which begin and end escape codes, read in chunks of
+        // varying size and applied to a small terminal. Set NOTTY_FUZZ_ROUNDS to run more rounds.
+        static BYTES: &'static [u8] = b"\x1b\x07\x9b\x9d\x90\xc2\xe2\x80\xff[]P{}^_;:.?>=$\"\
+                                        0123456789ABCDEFHJKLMPSTX@`abcdfghlmnqrstu \n\r\x08\x09\\";
+        let rounds = env::var("NOTTY_FUZZ_ROUNDS").ok().and_then(|n| n.parse().ok()).unwrap_or(200);
+        let mut rng = Lcg(0x2545f491);
+        for round in 0..rounds {
+            // Reported if the round fails, so that it can be reproduced.
+            let seed = rng.0;
+            let data: Vec<u8> = (0..256).map(|_| BYTES[rng.next() as usize % BYTES.len()])
+                                        .collect();
+            let mut terminal = Headless::new(rng.next() % 12 + 1, rng.next() % 6 + 1);
+            let chunk = rng.next() as usize % 64 + 1;
+            for cmd in Output::new(BufReader::with_capacity(chunk, &data[..])) {
+                let cmd = cmd.unwrap_or_else(|e| panic!("round {} seed {:#x}: {}", round, seed, e));
+                cmd.apply(terminal.terminal_mut()).unwrap_or_else(|e| {
+                    panic!("round {} seed {:#x}: {}: {}", round, seed, cmd.repr(), e)
+                });
+            }
+            terminal.snapshot();
+        }
+    }
+
 }
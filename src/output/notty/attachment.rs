@@ -44,11 +44,12 @@ impl Attachments {
                 None    => return None,
             }
         }
+        // An empty or overlong length is treated as 0, for the same reason.
         let len = usize::from_str_radix(unsafe {
             str::from_utf8_unchecked(&buf[*offset + 1 .. offset_tmp])
-        }, 16).unwrap();
+        }, 16).unwrap_or(0);
         offset_tmp += 1;
-        if offset_tmp + len > buf.len() {
+        if len > buf.len() - offset_tmp {
             let data = &buf[offset_tmp..];
             self.data.extend(data);
             *offset = buf.len();
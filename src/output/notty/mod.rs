@@ -40,16 +40,25 @@ impl NottyCode {
             Some(0x14)  => {
                 let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
                 let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
-                let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
+                let p = match MediaPosition::decode(args.next(), Some(MediaPosition::default())) {
+                    Some(p) => p,
+                    None    => return None,
+                };
                 if let Some(img) = image(self.attachments.iter()) {
                     wrap(Some(Put::new_image(img, p, w, h)))
                 } else { None }
             }
             Some(0x15)  => {
-                let coords = Coords::decode(args.next(), Some(Coords {x: 0, y: 0})).unwrap();
+                let coords = match Coords::decode(args.next(), Some(Coords {x: 0, y: 0})) {
+                    Some(coords)    => coords,
+                    None            => return None,
+                };
                 let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
                 let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
-                let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
+                let p = match MediaPosition::decode(args.next(), Some(MediaPosition::default())) {
+                    Some(p) => p,
+                    None    => return None,
+                };
                 if let Some(img) = image(self.attachments.iter()) {
                     wrap(Some(PutAt::new_image(img, p, w, h, coords)))
                 } else { None }
@@ -58,8 +67,14 @@ impl NottyCode {
                 wrap(Movement::decode(args.next(), Some(To(Right, 1, true))).map(Move::new))
             }
             Some(0x19)  => {
-                let dir = Direction::decode(args.next(), Some(Down)).unwrap();
-                let n = u32::decode(args.next(), Some(1)).unwrap();
+                let dir = match Direction::decode(args.next(), Some(Down)) {
+                    Some(dir)   => dir,
+                    None        => return None,
+                };
+                let n = match u32::decode(args.next(), Some(1)) {
+                    Some(n) => n,
+                    None    => return None,
+                };
                 wrap(Some(ScrollScreen::new(dir, n)))
             }
             Some(0x20)  => {
@@ -69,14 +84,20 @@ impl NottyCode {
                 wrap(u32::decode(args.next(), Some(1)).map(RemoveChars::new))
             }
             Some(0x22)  => {
-                let n = u32::decode(args.next(), Some(1)).unwrap();
+                let n = match u32::decode(args.next(), Some(1)) {
+                    Some(n) => n,
+                    None    => return None,
+                };
                 wrap(bool::decode(args.next(), Some(true)).map(|f| RemoveRows::new(n, f)))
             }
             Some(0x26)  => {
                 wrap(u32::decode(args.next(), Some(1)).map(InsertBlank::new))
             }
             Some(0x27)  => {
-                let n = u32::decode(args.next(), Some(1)).unwrap();
+                let n = match u32::decode(args.next(), Some(1)) {
+                    Some(n) => n,
+                    None    => return None,
+                };
                 wrap(bool::decode(args.next(), Some(true)).map(|f| InsertRows::new(n, f)))
             }
             Some(0x30)  => {
@@ -92,7 +113,10 @@ impl NottyCode {
                 }
             }
             Some(0x32)  => {
-                let area = Area::decode(args.next(), Some(WholeScreen)).unwrap();
+                let area = match Area::decode(args.next(), Some(WholeScreen)) {
+                    Some(area)  => area,
+                    None        => return None,
+                };
                 match Style::decode(args.next(), None) {
                     Some(style) => wrap(Some(SetStyleInArea(area, style))),
                     None        => wrap(Some(DefaultStyleInArea(area))),
@@ -105,14 +129,20 @@ impl NottyCode {
                 })
             }
             Some(0x50)  => {
-                let coords = Coords::decode(args.next(), None).unwrap();
+                let coords = match Coords::decode(args.next(), None) {
+                    Some(coords)    => coords,
+                    None            => return None,
+                };
                 self.attachments.iter().next().and_then(|data| str::from_utf8(data).ok())
                 .and_then(|string| {
                     wrap(Some(AddToolTip(coords, RefCell::new(Some(String::from(string))))))
                 })
             }
             Some(0x51)  => {
-                let coords = Coords::decode(args.next(), None).unwrap();
+                let coords = match Coords::decode(args.next(), None) {
+                    Some(coords)    => coords,
+                    None            => return None,
+                };
                 self.attachments.iter().map(|data| str::from_utf8(data).ok().map(String::from))
                 .collect::<Option<_>>().and_then(|data| wrap(Some(AddDropDown {
                     coords: coords,
@@ -122,7 +152,10 @@ impl NottyCode {
             Some(0x54)  => wrap(Coords::decode(args.next(), None).map(RemoveToolTip)),
             Some(0x60)  => wrap(bool::decode(args.next(), Some(false)).map(PushBuffer)),
             Some(0x61)  => wrap(Some(PopBuffer)),
-            Some(0x80)  => wrap(InputMode::decode(args.next(), Some(Ansi(false))).map(SetInputMode)),
+            Some(0x80)  => match InputMode::decode(args.next(), Some(Ansi(false))) {
+                Some(Notty(_))  => wrap(u32::decode(args.next(), Some(0)).map(SetNottyInputMode)),
+                mode            => wrap(mode.map(SetInputMode)),
+            },
             Some(0x84)  => wrap(Some(SetBufferMode(BufferSettings::decode(args.next(), None)))),
             Some(0x88)  => wrap(Some(SetEchoMode(EchoSettings::decode(args.next(), None)))),
             _           => None,
@@ -14,7 +14,7 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use cfg;
-use datatypes::{Coords, Movement, move_within};
+use datatypes::{Coords, Direction, Movement, move_within};
 use datatypes::Direction::*;
 use datatypes::Movement::*;
 use terminal::{CharCell, Grid, Styles};
@@ -28,25 +28,31 @@ pub struct Cursor {
 
 impl Cursor {
 
-    pub fn navigate(&mut self, grid: &mut Grid<CharCell>, movement: Movement) {
+    /// The direction and number of rows or columns the grid must scroll to carry out a movement
+    /// which indexes past its edge.
+    pub fn scroll_needed(&self, grid: &Grid<CharCell>, movement: Movement)
+            -> Option<(Direction, u32)> {
         match movement {
             IndexTo(Up, n) | PreviousLine(n) if n > self.coords.y => {
-                let n = n - self.coords.y;
-                grid.scroll(n as usize, Up);
+                Some((Up, n - self.coords.y))
             }
-            IndexTo(Down, n) | NextLine(n) if self.coords.y + n >= grid.height as u32 => {
-                let n = self.coords.y + n - grid.height as u32 + 1;
-                grid.scroll(n as usize, Down);
+            IndexTo(Down, n) | NextLine(n)
+                    if self.coords.y.saturating_add(n) >= grid.height as u32 => {
+                Some((Down, self.coords.y.saturating_add(n) - grid.height as u32 + 1))
             }
             IndexTo(Left, n) if n > self.coords.x => {
-                let n = n - self.coords.x;
-                grid.scroll(n as usize, Left);
+                Some((Left, n - self.coords.x))
             }
-            IndexTo(Right, n) if self.coords.x + n >= grid.width as u32 => {
-                let n = self.coords.x + n - grid.width as u32 + 1;
-                grid.scroll(n as usize, Right);
+            IndexTo(Right, n) if self.coords.x.saturating_add(n) >= grid.width as u32 => {
+                Some((Right, self.coords.x.saturating_add(n) - grid.width as u32 + 1))
             }
-            _   => (),
+            _   => None,
+        }
+    }
+
+    pub fn navigate(&mut self, grid: &mut Grid<CharCell>, movement: Movement) {
+        if let Some((dir, n)) = self.scroll_needed(grid, movement) {
+            grid.scroll(n as usize, dir);
         }
         let mut coords = move_within(self.coords, movement, grid.bounds());
 
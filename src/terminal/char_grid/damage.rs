@@ -0,0 +1,117 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::iter;
+
+/// The parts of the view which have changed since damage was last taken.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Damage {
+    /// Every row in view has changed.
+    pub all: bool,
+    /// The number of rows the contents of the view moved up before the rows in `rows` changed.
+    /// A frontend can move the rows it has already drawn instead of drawing them again.
+    pub scrolled: u32,
+    /// The rows in view which have changed, counting from the top of the view.
+    pub rows: Vec<u32>,
+}
+
+impl Damage {
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.scrolled == 0 && self.rows.is_empty()
+    }
+}
+
+pub struct DamageTracker {
+    all: bool,
+    scrolled: u32,
+    rows: Vec<bool>,
+}
+
+impl DamageTracker {
+    pub fn new(height: u32) -> DamageTracker {
+        DamageTracker {
+            all: true,
+            scrolled: 0,
+            rows: vec![false; height as usize],
+        }
+    }
+
+    pub fn row(&mut self, y: u32) {
+        if let Some(row) = self.rows.get_mut(y as usize) { *row = true; }
+    }
+
+    pub fn all(&mut self) {
+        self.all = true;
+    }
+
+    pub fn resize(&mut self, height: u32) {
+        self.rows = vec![false; height as usize];
+        self.all = true;
+    }
+
+    /// Note that the contents of the view moved up `n` rows, bringing `n` new rows into view at
+    /// the bottom.
+    pub fn scroll(&mut self, n: u32) {
+        if self.all { return; }
+        let n = n as usize;
+        if n >= self.rows.len() {
+            self.all = true;
+            return;
+        }
+        self.rows.drain(..n);
+        self.rows.extend(iter::repeat(true).take(n));
+        self.scrolled += n as u32;
+    }
+
+    pub fn take(&mut self) -> Damage {
+        let damage = Damage {
+            all: self.all,
+            scrolled: if self.all { 0 } else { self.scrolled },
+            rows: if self.all { Vec::new() } else {
+                self.rows.iter().enumerate().filter(|&(_, &row)| row).map(|(y, _)| {
+                    y as u32
+                }).collect()
+            },
+        };
+        self.all = false;
+        self.scrolled = 0;
+        for row in &mut self.rows { *row = false; }
+        damage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn take() {
+        let mut tracker = DamageTracker::new(4);
+        assert!(tracker.take().all);
+        assert!(tracker.take().is_empty());
+        tracker.row(1);
+        tracker.row(3);
+        tracker.row(9);
+        tracker.scroll(1);
+        tracker.row(1);
+        let damage = tracker.take();
+        assert_eq!(damage.scrolled, 1);
+        assert_eq!(damage.rows, [0, 1, 2, 3]);
+        tracker.scroll(4);
+        assert_eq!(tracker.take(), Damage { all: true, scrolled: 0, rows: Vec::new() });
+    }
+
+}
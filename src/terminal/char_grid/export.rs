@@ -0,0 +1,193 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use cfg;
+use datatypes::{Color, hex_color};
+use terminal::{CharCell, Styles};
+
+/// How text taken from the grid is written.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextFormat {
+    /// Only the text of the cells.
+    Plain,
+    /// Text with SGR escape sequences for the styles of the cells.
+    Ansi,
+    /// An HTML `pre` element, with spans giving the styles of the cells inline.
+    Html,
+}
+
+pub struct TextWriter {
+    format: TextFormat,
+    out: String,
+    style: Styles,
+}
+
+impl TextWriter {
+    pub fn new(format: TextFormat) -> TextWriter {
+        let out = match format {
+            TextFormat::Html    => String::from("<pre>"),
+            _                   => String::new(),
+        };
+        TextWriter {
+            format: format,
+            out: out,
+            style: Styles::default(),
+        }
+    }
+
+    /// Write the text of a cell. The extension cells of wide characters have no text of their
+    /// own, and images are written as U+FFFC.
+    pub fn push_cell(&mut self, cell: &CharCell) {
+        match *cell {
+            CharCell::Empty(style)              => self.push(" ", style),
+            CharCell::Char(c, style)            => self.push(&c.to_string(), style),
+            CharCell::Grapheme(ref s, style)    => self.push(s, style),
+            CharCell::Image { style, .. }       => self.push("\u{fffc}", style),
+            CharCell::Extension(..)             => (),
+        }
+    }
+
+    pub fn newline(&mut self) {
+        self.out.push('\n');
+    }
+
+    pub fn finish(mut self) -> String {
+        if self.style != Styles::default() { self.set_style(Styles::default()); }
+        if self.format == TextFormat::Html { self.out.push_str("</pre>"); }
+        self.out
+    }
+
+    fn push(&mut self, text: &str, style: Styles) {
+        if style != self.style { self.set_style(style); }
+        match self.format {
+            TextFormat::Html    => for c in text.chars() {
+                match c {
+                    '&' => self.out.push_str("&amp;"),
+                    '<' => self.out.push_str("&lt;"),
+                    '>' => self.out.push_str("&gt;"),
+                    '"' => self.out.push_str("&quot;"),
+                    c   => self.out.push(c),
+                }
+            },
+            _                   => self.out.push_str(text),
+        }
+    }
+
+    fn set_style(&mut self, style: Styles) {
+        let default = Styles::default();
+        match self.format {
+            TextFormat::Plain   => (),
+            TextFormat::Ansi    => {
+                self.out.push_str("\x1b[0");
+                if style != default { self.out.push_str(&sgr(&style)); }
+                self.out.push('m');
+            }
+            TextFormat::Html    => {
+                if self.style != default { self.out.push_str("</span>"); }
+                if style != default {
+                    self.out.push_str(&format!("<span style=\"{}\">", css(&style)));
+                }
+            }
+        }
+        self.style = style;
+    }
+}
+
+// The SGR parameters which set a style, after a reset.
+fn sgr(style: &Styles) -> String {
+    let mut params = String::new();
+    for &(on, n) in &[(style.bold, 1), (style.italic, 3), (style.underline, 4),
+                      (style.blink, 5), (style.inverted, 7), (style.opacity == 0, 8),
+                      (style.strikethrough, 9), (style.double_underline, 21)] {
+        if on { params.push_str(&format!(";{}", n)); }
+    }
+    if style.fg_color != cfg::DEFAULT_FG {
+        let Color(r, g, b) = style.fg_color;
+        params.push_str(&format!(";38;2;{};{};{}", r, g, b));
+    }
+    if style.bg_color != cfg::DEFAULT_BG {
+        let Color(r, g, b) = style.bg_color;
+        params.push_str(&format!(";48;2;{};{};{}", r, g, b));
+    }
+    params
+}
+
+// The inline CSS for a style.
+fn css(style: &Styles) -> String {
+    let (fg, bg) = if style.inverted {
+        (style.bg_color, style.fg_color)
+    } else { (style.fg_color, style.bg_color) };
+    let mut css = String::new();
+    if fg != cfg::DEFAULT_FG || style.inverted {
+        css.push_str(&format!("color:{};", hex_color(fg)));
+    }
+    if bg != cfg::DEFAULT_BG || style.inverted {
+        css.push_str(&format!("background-color:{};", hex_color(bg)));
+    }
+    if style.bold { css.push_str("font-weight:bold;"); }
+    if style.italic { css.push_str("font-style:italic;"); }
+    let mut lines = Vec::new();
+    if style.underline { lines.push("underline"); }
+    if style.double_underline { lines.push("underline double"); }
+    if style.strikethrough { lines.push("line-through"); }
+    if !lines.is_empty() { css.push_str(&format!("text-decoration:{};", lines.join(" "))); }
+    if style.opacity != 0xff {
+        css.push_str(&format!("opacity:{:.2};", style.opacity as f32 / 255.0));
+    }
+    css
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use datatypes::{Color, Coords};
+    use terminal::{CharCell, Styles};
+
+    fn write(format: TextFormat) -> String {
+        let bold = Styles { bold: true, fg_color: Color(0xff, 0, 0), ..Styles::default() };
+        let mut writer = TextWriter::new(format);
+        writer.push_cell(&CharCell::Char('<', Styles::default()));
+        writer.push_cell(&CharCell::Char('a', bold));
+        writer.newline();
+        writer.push_cell(&CharCell::Char('b', bold));
+        writer.push_cell(&CharCell::Char('\u{4e00}', Styles::default()));
+        writer.push_cell(&CharCell::Extension(Coords { x: 1, y: 1 }, Styles::default()));
+        writer.finish()
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(write(TextFormat::Plain), "<a\nb\u{4e00}");
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(write(TextFormat::Ansi),
+                   "<\x1b[0;1;38;2;255;0;0ma\nb\x1b[0m\u{4e00}");
+        let mut writer = TextWriter::new(TextFormat::Ansi);
+        writer.push_cell(&CharCell::Char('h', Styles { opacity: 0, ..Styles::default() }));
+        assert_eq!(writer.finish(), "\x1b[0;8mh\x1b[0m");
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(write(TextFormat::Html),
+                   "<pre>&lt;<span style=\"color:#ff0000;font-weight:bold;\">a\nb</span>\
+                    \u{4e00}</pre>");
+    }
+
+}
@@ -27,6 +27,7 @@ pub struct Grid<T> {
     pub scrolls_x: bool,
     pub scrolls_y: bool,
     data: VecDeque<T>,
+    wrapped: VecDeque<bool>,
     rem_x: usize,
     rem_y: usize,
 }
@@ -52,11 +53,18 @@ impl<T: Default + Clone> Grid<T> {
             scrolls_x: max_x != 0,
             scrolls_y: max_y != 0,
             data: iter::repeat(T::default()).take(w * h).collect(),
+            wrapped: iter::repeat(false).take(h).collect(),
             rem_x: max_x.saturating_sub(w),
             rem_y: max_y.saturating_sub(h),
         }
     }
 
+    /// Whether the grid can grow wider to make room to the right of its last column. A grid
+    /// which scrolls horizontally stops growing once it is as wide as it may be.
+    pub fn can_extend_x(&self) -> bool {
+        self.scrolls_x && self.rem_x != 0
+    }
+
     pub fn bounds(&self) -> Region {
         Region::new(0, 0, self.width as u32, self.height as u32)
     }
@@ -64,6 +72,10 @@ impl<T: Default + Clone> Grid<T> {
     pub fn add_to_top(&mut self, data: Vec<T>) {
         assert!(data.len() % self.width == 0);
         self.height += data.len() / self.width;
+        if self.scrolls_y { self.rem_y = self.rem_y.saturating_sub(data.len() / self.width); }
+        for _ in 0..(data.len() / self.width) {
+            self.wrapped.push_front(false);
+        }
         for item in data {
             self.data.push_front(item);
         }
@@ -72,6 +84,10 @@ impl<T: Default + Clone> Grid<T> {
     pub fn add_to_bottom(&mut self, data: Vec<T>) {
         assert!(data.len() % self.width == 0);
         self.height += data.len() / self.width;
+        if self.scrolls_y { self.rem_y = self.rem_y.saturating_sub(data.len() / self.width); }
+        for _ in 0..(data.len() / self.width) {
+            self.wrapped.push_back(false);
+        }
         for item in data {
             self.data.push_back(item);
         }
@@ -80,6 +96,8 @@ impl<T: Default + Clone> Grid<T> {
     pub fn remove_from_top(&mut self, n: usize) -> Vec<T> {
         assert!(n < self.height);
         self.height -= n;
+        if self.scrolls_y { self.rem_y = self.rem_y.saturating_add(n); }
+        self.wrapped.drain(..n);
         let n = n * self.width;
         self.data.drain(..n).collect()
     }
@@ -87,15 +105,73 @@ impl<T: Default + Clone> Grid<T> {
     pub fn remove_from_bottom(&mut self, n: usize) -> Vec<T> {
         assert!(n < self.height);
         self.height -= n;
+        if self.scrolls_y { self.rem_y = self.rem_y.saturating_add(n); }
+        self.wrapped.truncate(self.height);
         let n = self.data.len() - (n * self.width);
         self.data.drain(n..).collect()
     }
 
+    /// Drop the rows above the bottom `visible` rows of a grid which scrolls vertically,
+    /// returning the number of rows dropped.
+    pub fn truncate_scrollback(&mut self, visible: usize) -> usize {
+        if !self.scrolls_y || self.height <= visible { return 0; }
+        let n = self.height - visible;
+        self.data.drain(..n * self.width);
+        self.wrapped.drain(..n);
+        self.height = visible;
+        self.rem_y = self.rem_y.saturating_add(n);
+        n
+    }
+
+    /// Replace the contents of the grid with rows of a new width. The number of rows the grid
+    /// may grow to by scrolling stays the same.
+    pub fn reflow(&mut self, width: usize, data: Vec<T>, wrapped: Vec<bool>) {
+        assert!(data.len() == width * wrapped.len());
+        let max_height = self.max_height();
+        self.width = width;
+        self.height = wrapped.len();
+        self.rem_y = max_height.saturating_sub(self.height);
+        self.data = data.into_iter().collect();
+        self.wrapped = wrapped.into_iter().collect();
+    }
+
+    /// The number of rows this grid can hold, including rows it can add by scrolling.
+    pub fn max_height(&self) -> usize {
+        self.height.saturating_add(self.rem_y)
+    }
+
+    /// Set the number of rows this grid can grow to by scrolling. This does not remove rows.
+    pub fn set_max_height(&mut self, max: usize) {
+        self.rem_y = max.saturating_sub(self.height);
+    }
+
+    /// The number of columns this grid can hold, including columns it can add by scrolling.
+    pub fn max_width(&self) -> usize {
+        self.width.saturating_add(self.rem_x)
+    }
+
+    /// Set the number of columns this grid can grow to by scrolling. This does not remove
+    /// columns.
+    pub fn set_max_width(&mut self, max: usize) {
+        self.rem_x = max.saturating_sub(self.width);
+    }
+
+    /// Whether row `y` was soft-wrapped, that is, whether the line it holds continues on the
+    /// next row.
+    pub fn is_wrapped(&self, y: usize) -> bool {
+        self.wrapped[y]
+    }
+
+    pub fn set_wrapped(&mut self, y: usize, wrapped: bool) {
+        self.wrapped[y] = wrapped;
+    }
+
     pub fn add_to_left(&mut self, data: Vec<T>) {
         assert!(data.len() % self.height == 0);
         let extra_width = data.len() / self.height;
         let width = self.width;
         self.width += extra_width;
+        if self.scrolls_x { self.rem_x = self.rem_x.saturating_sub(extra_width); }
         let iter = data.into_iter().enumerate().map(|(idx, item)| {
             ((idx / extra_width) * width, item)
         }).rev();
@@ -109,6 +185,7 @@ impl<T: Default + Clone> Grid<T> {
         let width = self.width;
         let len = self.data.len();
         self.width -= n;
+        if self.scrolls_x { self.rem_x += n; }
         (0..len).filter(|&x| (x % width) < n)
                 .rev().map(|idx| self.data.remove(idx).unwrap())
                 .collect()
@@ -119,6 +196,7 @@ impl<T: Default + Clone> Grid<T> {
         let extra_width = data.len() / self.height;
         let width = self.width;
         self.width += extra_width;
+        if self.scrolls_x { self.rem_x = self.rem_x.saturating_sub(extra_width); }
         let iter = data.into_iter().enumerate().map(|(idx, item)| {
             ((idx / extra_width) * width + width, item)
         }).rev();
@@ -132,6 +210,7 @@ impl<T: Default + Clone> Grid<T> {
         let width = self.width;
         let len = self.data.len();
         self.width -= n;
+        if self.scrolls_x { self.rem_x += n; }
         (0..len).filter(|&x| (x % width) >= width - n)
                 .rev().map(|idx| self.data.remove(idx).unwrap())
                 .collect()
@@ -141,16 +220,12 @@ impl<T: Default + Clone> Grid<T> {
         use datatypes::Direction::*;
         match direction {
             Up if self.rem_y != 0           => self.extend_up(n),
-            Up if n >= self.height          => self.data.clear(),
             Up                              => self.shift_up(n),
             Down if self.rem_y != 0         => self.extend_down(n),
-            Down if n >= self.height        => self.data.clear(),
             Down                            => self.shift_down(n),
             Left if self.rem_x != 0         => self.extend_left(n),
-            Left if n >= self.width         => self.data.clear(),
             Left                            => self.shift_left(n),
             Right if self.rem_x != 0        => self.extend_right(n),
-            Right if n >= self.width        => self.data.clear(),
             Right                           => self.shift_right(n),
         }
     }
@@ -164,6 +239,9 @@ impl<T: Default + Clone> Grid<T> {
         for _ in 0..(rem_or_n * self.width) {
             self.data.push_front(T::default());
         }
+        for _ in 0..rem_or_n {
+            self.wrapped.push_front(false);
+        }
         self.height += rem_or_n;
         if n > self.rem_y {
             let rem = n - self.rem_y;
@@ -177,6 +255,9 @@ impl<T: Default + Clone> Grid<T> {
         for _ in 0..(rem_or_n * self.width) {
             self.data.push_back(T::default());
         }
+        for _ in 0..rem_or_n {
+            self.wrapped.push_back(false);
+        }
         self.height += rem_or_n;
         if n > self.rem_y {
             let rem = n - self.rem_y;
@@ -198,7 +279,7 @@ impl<T: Default + Clone> Grid<T> {
             let rem = n - self.rem_x;
             self.shift_left(rem);
         }
-        self.rem_y = self.rem_y.saturating_sub(n);
+        self.rem_x = self.rem_x.saturating_sub(n);
     }
 
     fn extend_right(&mut self, n: usize) {
@@ -214,24 +295,35 @@ impl<T: Default + Clone> Grid<T> {
             let rem = n - self.rem_x;
             self.shift_right(rem);
         }
-        self.rem_y = self.rem_y.saturating_sub(n);
+        self.rem_x = self.rem_x.saturating_sub(n);
     }
 
     fn shift_up(&mut self, n: usize) {
+        let n = cmp::min(n, self.height);
         for _ in 0..(n * self.width) {
             self.data.pop_back();
             self.data.push_front(T::default());
         }
+        for _ in 0..n {
+            self.wrapped.pop_back();
+            self.wrapped.push_front(false);
+        }
     }
 
     fn shift_down(&mut self, n: usize) {
+        let n = cmp::min(n, self.height);
         for _ in 0..(n * self.width) {
             self.data.pop_front();
             self.data.push_back(T::default());
         }
+        for _ in 0..n {
+            self.wrapped.pop_front();
+            self.wrapped.push_back(false);
+        }
     }
 
     fn shift_left(&mut self, n: usize) {
+        let n = cmp::min(n, self.width);
         for _ in 0..n {
             self.data.pop_back();
             self.data.push_front(T::default());
@@ -242,6 +334,7 @@ impl<T: Default + Clone> Grid<T> {
     }
 
     fn shift_right(&mut self, n: usize) {
+        let n = cmp::min(n, self.width);
         for _ in 0..n {
             self.data.pop_front();
             self.data.push_back(T::default());
@@ -331,6 +424,47 @@ mod tests {
         }, 8, 9);
     }
 
+    #[test]
+    fn truncate_scrollback() {
+        let mut grid = Grid::with_y_cap(8, 8, 10);
+        grid.scroll(2, Down);
+        grid[Coords {x:0, y:2}] = 1;
+        assert_eq!(grid.truncate_scrollback(8), 2);
+        assert_eq!(grid.height, 8);
+        assert_eq!(grid.data.len(), 64);
+        assert_eq!(grid[Coords {x:0, y:0}], 1);
+        grid.scroll(2, Down);
+        assert_eq!(grid.height, 10);
+        let mut grid = Grid::<i32>::new(8, 8);
+        assert_eq!(grid.truncate_scrollback(6), 0);
+        assert_eq!(grid.height, 8);
+    }
+
+    #[test]
+    fn wrapped_rows() {
+        let mut grid = Grid::<i32>::with_y_cap(8, 8, 10);
+        grid.set_wrapped(7, true);
+        grid.scroll(1, Down);
+        assert!(grid.is_wrapped(7));
+        assert!(!grid.is_wrapped(8));
+        grid.remove_from_top(2);
+        assert!(grid.is_wrapped(5));
+        grid.add_to_top(vec![0; 8]);
+        assert!(grid.is_wrapped(6));
+        assert_eq!(grid.wrapped.len(), grid.height);
+    }
+
+    #[test]
+    fn reflow() {
+        let mut grid = Grid::with_y_cap(8, 8, 10);
+        grid.reflow(4, vec![1; 12], vec![true, false, false]);
+        assert_eq!(grid.width, 4);
+        assert_eq!(grid.height, 3);
+        assert_eq!(grid.max_height(), 10);
+        assert!(grid.is_wrapped(0));
+        assert_eq!(grid[Coords {x:3, y:2}], 1);
+    }
+
     #[test]
     fn add_to_left() {
         run_test(|mut grid, _, height| {
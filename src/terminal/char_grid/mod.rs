@@ -13,9 +13,11 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use std::cmp::Ordering;
+use std::cmp::{self, Ordering};
 use std::collections::HashMap;
+use std::usize;
 
+use regex;
 use unicode_width::*;
 
 use cfg;
@@ -26,77 +28,698 @@ use datatypes::Direction::*;
 
 mod cell;
 mod cursor;
+mod damage;
+mod export;
 mod grid;
+mod scrollback;
+mod search;
+mod selection;
+mod state;
 mod styles;
 mod tooltip;
+mod view;
 
 pub use self::cell::CharCell;
 pub use self::cursor::Cursor;
+pub use self::damage::Damage;
+pub use self::export::TextFormat;
 pub use self::grid::Grid;
+pub use self::scrollback::Scrollback;
+pub use self::search::{SearchMatch, SearchPattern};
+pub use self::selection::{Selection, SelectionMode};
+pub use self::state::{CellState, CursorState, GridState, RowState, StateError, StylesState};
 pub use self::styles::Styles;
 pub use self::tooltip::Tooltip;
+pub use self::view::ViewCells;
+
+use self::damage::DamageTracker;
+use self::export::TextWriter;
+use self::scrollback::{CompactRow, History};
+use self::search::Search;
 
 pub struct CharGrid {
     grid: Grid<CharCell>,
     cursor: Cursor,
     tooltips: HashMap<Coords, Tooltip>,
+    links: HashMap<Coords, String>,
+    hyperlink: Option<String>,
+    scrollback: Option<Scrollback>,
+    history: History,
+    rows_dropped: usize,
+    search: Option<Search>,
+    search_stale: bool,
+    selection: Option<Selection>,
+    word_separators: String,
+    damage: DamageTracker,
+    wrapped_from: Option<u32>,
+    visible_height: u32,
+    visible_width: u32,
+    scroll_x: u32,
+    view_offset: u32,
     pub grid_width: u32,
     pub grid_height: u32,
 }
 
 impl CharGrid {
     pub fn new(w: u32, h: u32, scroll_x: bool, scroll_y: bool) -> CharGrid {
-        let grid = match (scroll_x, scroll_y) {
-            (false, false)  => Grid::new(w as usize, h as usize),
-            (false, true)   => Grid::with_y_cap(w as usize, h as usize, cfg::SCROLLBACK as usize),
-            (true, false)   => unimplemented!(),
-            (true, true)    => unimplemented!(),
-        };
+        let scrollback = if scroll_y { Some(Scrollback::default()) } else { None };
+        CharGrid::with_scrollback(w, h, scroll_x, scrollback)
+    }
+
+    /// Create a grid which keeps the given scrollback, or which does not scroll vertically if
+    /// `scrollback` is `None`.
+    pub fn with_scrollback(w: u32, h: u32, scroll_x: bool, scrollback: Option<Scrollback>)
+            -> CharGrid {
+        let max_x = if scroll_x { cfg::MAX_WIDTH as usize } else { 0 };
+        let max_y = scrollback.map_or(0, |s| s.max_rows(h as usize).unwrap_or(usize::MAX));
         CharGrid {
-            grid: grid,
+            grid: Grid::with_x_y_caps(w as usize, h as usize, max_x, max_y),
             cursor: Cursor::default(),
             tooltips: HashMap::new(),
+            links: HashMap::new(),
+            hyperlink: None,
+            scrollback: scrollback,
+            history: History::default(),
+            rows_dropped: 0,
+            search: None,
+            search_stale: false,
+            selection: None,
+            word_separators: String::from(cfg::WORD_SEPARATORS),
+            damage: DamageTracker::new(h),
+            wrapped_from: None,
+            visible_height: h,
+            visible_width: w,
+            scroll_x: 0,
+            view_offset: 0,
             grid_width: w,
             grid_height: h,
         }
     }
 
+    /// Set the number of visible rows. In a grid which scrolls, the visible rows are the rows at
+    /// the bottom of the grid, and the rest are scrollback.
+    ///
+    /// When shrinking, blank rows below the cursor are removed first. After that, a scrolling
+    /// grid keeps its remaining rows as scrollback, while other grids lose rows from the top.
+    /// When growing, a scrolling grid shows rows from its scrollback before adding blank rows.
     pub fn set_height(&mut self, h: u32) {
-        if self.grid.scrolls_y { return; }
-        match self.grid_height.cmp(&h) {
+        if h == 0 { return; }
+        let h = h as usize;
+        let visible = self.visible_height as usize;
+        match visible.cmp(&h) {
             Ordering::Greater   => {
-                let n = (self.grid_height - h) as usize;
-                self.grid.remove_from_bottom(n);
+                let n = visible - h;
+                let cursor = self.cursor.coords.y as usize;
+                let blank = (cursor + 1..self.grid.height).rev().take_while(|&y| {
+                    (0..self.grid.width).all(|x| {
+                        self.grid[Coords { x: x as u32, y: y as u32 }].is_empty()
+                    })
+                }).count();
+                let bottom = cmp::min(cmp::min(n, blank), self.grid.height.saturating_sub(h));
+                if bottom > 0 {
+                    self.grid.remove_from_bottom(bottom);
+                    let height = self.grid.height as u32;
+                    self.tooltips = self.tooltips.drain().filter(|&(coords, _)| {
+                        coords.y < height
+                    }).collect();
+                    self.links = self.links.drain().filter(|&(coords, _)| {
+                        coords.y < height
+                    }).collect();
+                }
+                if !self.grid.scrolls_y && n > bottom {
+                    let top = n - bottom;
+                    self.grid.remove_from_top(top);
+                    self.rows_removed_from_top(top as u32);
+                }
             }
             Ordering::Equal     => (),
             Ordering::Less      => {
-                let n = ((h - self.grid_height) * self.grid_width) as usize;
-                self.grid.add_to_bottom(vec![CharCell::default(); n]);
+                let n = if self.grid.scrolls_y {
+                    let n = h.saturating_sub(self.grid.height);
+                    n - self.restore_scrollback(n)
+                } else { h - visible };
+                self.grid.add_to_bottom(vec![CharCell::default(); n * self.grid.width]);
             }
         }
-        self.grid_height = h;
+        self.visible_height = h as u32;
+        self.grid_height = self.grid.height as u32;
+        self.damage.resize(h as u32);
+        self.compact_scrollback();
     }
 
+    /// Set the number of visible columns. A grid which scrolls horizontally never loses columns
+    /// this way; it only grows if it is narrower than the new width. Other grids are reflowed.
     pub fn set_width(&mut self, w: u32) {
-        if self.grid.scrolls_x { return; }
-        match self.grid_width.cmp(&w) {
-            Ordering::Greater   => {
-                let n = (self.grid_width - w) as usize;
-                self.grid.remove_from_right(n);
-            }
-            Ordering::Equal     => (),
-            Ordering::Less      => {
-                let n = ((w - self.grid_width) * self.grid_height) as usize;
+        if w == 0 { return; }
+        if self.grid.scrolls_x {
+            if w as usize > self.grid.width {
+                let n = (w as usize - self.grid.width) * self.grid.height;
                 self.grid.add_to_right(vec![CharCell::default(); n]);
             }
+        } else if w != self.grid_width {
+            self.reflow(w as usize);
+        }
+        self.visible_width = w;
+        self.damage.all();
+        self.follow_cursor();
+    }
+
+    /// Change how many columns this grid can grow to, which is `cfg::MAX_WIDTH` at first. This
+    /// does not remove columns, and has no effect on a grid which does not scroll horizontally.
+    pub fn set_max_width(&mut self, max: u32) {
+        if !self.grid.scrolls_x { return; }
+        self.grid.set_max_width(max as usize);
+    }
+
+    /// Change how much scrollback this grid keeps, evicting the oldest rows if it now holds too
+    /// many. This has no effect on a grid which does not scroll vertically.
+    pub fn set_scrollback(&mut self, scrollback: Scrollback) {
+        if !self.grid.scrolls_y { return; }
+        self.scrollback = Some(scrollback);
+        let max_rows = scrollback.max_rows(self.visible_height as usize).unwrap_or(usize::MAX);
+        self.grid.set_max_height(max_rows);
+        self.compact_scrollback();
+    }
+
+    // Move rows which are more than a screen above the visible area into the history as compact
+    // rows, and drop the oldest rows beyond what the scrollback settings allow.
+    fn compact_scrollback(&mut self) {
+        if let Some(scrollback) = self.scrollback {
+            let visible = self.visible_height as usize;
+            let max_rows = scrollback.max_rows(visible).unwrap_or(usize::MAX);
+            let excess = cmp::min(self.grid.height.saturating_sub(max_rows),
+                                  self.cursor.coords.y as usize);
+            if excess > 0 {
+                self.grid.remove_from_top(excess);
+                self.rows_removed_from_top(excess as u32);
+                self.count_dropped(excess);
+            }
+            let top = self.view_region().top as usize;
+            let n = cmp::min(cmp::min(self.grid.height.saturating_sub(visible * 2), top),
+                             self.cursor.coords.y as usize);
+            if n > 0 {
+                let width = self.grid.width;
+                let wrapped: Vec<bool> = (0..n).map(|y| self.grid.is_wrapped(y)).collect();
+                let cells = self.grid.remove_from_top(n);
+                for (y, (row, wrapped)) in cells.chunks(width).zip(wrapped).enumerate() {
+                    self.history.push_back(CompactRow::new(row, y as u32, wrapped));
+                }
+                self.rows_removed_from_top(n as u32);
+            }
+            let evicted = self.history.evict(scrollback, self.grid.height);
+            self.count_dropped(evicted);
+            self.grid_height = self.grid.height as u32;
+        }
+        let offset = cmp::min(self.view_offset, self.scrollback_height());
+        self.set_view_offset(offset);
+    }
+
+    // Keep the selection on the same cells as n rows are dropped from the top of the history,
+    // or from the top of the grid if its history is empty.
+    fn count_dropped(&mut self, n: usize) {
+        if n == 0 { return; }
+        self.rows_dropped = self.rows_dropped.wrapping_add(n);
+        if !self.selection.as_mut().map_or(true, |selection| selection.move_up(n as u32)) {
+            self.selection = None;
+        }
+    }
+
+    /// Move up to `n` of the newest rows of history back into the grid, above its other rows, so
+    /// that they can be viewed. Returns the number of rows restored. Restored rows are compacted
+    /// again once they are more than a screen above the visible area.
+    pub fn restore_scrollback(&mut self, n: usize) -> usize {
+        let n = cmp::min(n, self.history.len());
+        if n == 0 { return 0; }
+        self.rows_added_to_top(n as u32);
+        let width = self.grid.width;
+        let mut data = Vec::with_capacity(n * width);
+        let mut wrapped = Vec::with_capacity(n);
+        for y in (0..n).rev() {
+            let row = self.history.pop_back().unwrap();
+            data.extend(row.cells(y as u32, width).into_iter().rev());
+            wrapped.push(row.is_wrapped());
+        }
+        self.grid.add_to_top(data);
+        for (y, wrapped) in wrapped.into_iter().rev().enumerate() {
+            self.grid.set_wrapped(y, wrapped);
+        }
+        self.grid_height = self.grid.height as u32;
+        n
+    }
+
+    /// The number of rows of history stored outside of the grid.
+    pub fn scrollback_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// An estimate of the memory used by the history stored outside of the grid.
+    pub fn scrollback_bytes(&self) -> usize {
+        self.history.bytes()
+    }
+
+    /// The cells of row `n` of the history stored outside of the grid, counting from its oldest
+    /// row. Coordinates held by these cells count rows the same way.
+    pub fn scrollback_row(&self, n: usize) -> Option<Vec<CharCell>> {
+        self.history.get(n).map(|row| row.cells(n as u32, self.grid.width))
+    }
+
+    /// The number of rows of scrollback above the visible rows, whether they are held in the grid
+    /// or in its history.
+    pub fn scrollback_height(&self) -> u32 {
+        let above = self.grid.height.saturating_sub(self.visible_height as usize);
+        (self.history.len() + above) as u32
+    }
+
+    /// The number of rows the view is scrolled back from the bottom of the grid.
+    pub fn view_offset(&self) -> u32 {
+        self.view_offset
+    }
+
+    /// Scroll the view `n` rows back through the scrollback (`Up`) or towards the bottom of the
+    /// grid (`Down`). `Left` and `Right` pan the view of a grid which scrolls horizontally.
+    pub fn scroll_view(&mut self, dir: Direction, n: u32) {
+        match dir {
+            Up      => {
+                let offset = self.view_offset.saturating_add(n);
+                self.set_view_offset(offset);
+            }
+            Down    => {
+                let offset = self.view_offset.saturating_sub(n);
+                self.set_view_offset(offset);
+            }
+            Left    => {
+                let x = self.scroll_x.saturating_sub(n);
+                self.set_scroll_x(x);
+            }
+            Right   => {
+                let x = self.scroll_x.saturating_add(n);
+                self.set_scroll_x(x);
+            }
+        }
+    }
+
+    /// Scroll the view by `n` pages, a page being the number of visible rows or columns.
+    pub fn scroll_view_pages(&mut self, dir: Direction, n: u32) {
+        let page = match dir {
+            Up | Down       => self.visible_height,
+            Left | Right    => self.visible_width,
+        };
+        self.scroll_view(dir, page.saturating_mul(n));
+    }
+
+    /// Scroll the view to the oldest row of scrollback.
+    pub fn scroll_view_to_top(&mut self) {
+        let offset = self.scrollback_height();
+        self.set_view_offset(offset);
+    }
+
+    /// Scroll the view back to the bottom of the grid, where output appears.
+    pub fn scroll_view_to_bottom(&mut self) {
+        self.set_view_offset(0);
+    }
+
+    // Rows of history are moved back into the grid as they are scrolled into view.
+    fn set_view_offset(&mut self, offset: u32) {
+        let offset = cmp::min(offset, self.scrollback_height());
+        let above = self.grid.height.saturating_sub(self.visible_height as usize);
+        if offset as usize > above {
+            self.restore_scrollback(offset as usize - above);
+        }
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.damage.all();
+        }
+    }
+
+    /// The region of the grid which is in view.
+    pub fn view_region(&self) -> Region {
+        let bottom = self.grid.height.saturating_sub(self.view_offset as usize);
+        let top = bottom.saturating_sub(self.visible_height as usize);
+        let right = cmp::min(self.scroll_x + self.visible_width, self.grid.width as u32);
+        Region::new(self.scroll_x, top as u32, right, bottom as u32)
+    }
+
+    /// The cells in view, row by row.
+    pub fn view(&self) -> ViewCells {
+        ViewCells::new(&self.grid, self.view_region())
+    }
+
+    /// The position of the cursor within the view, if it is in view.
+    pub fn cursor_in_view(&self) -> Option<Coords> {
+        let region = self.view_region();
+        let coords = self.cursor.coords;
+        if region.left <= coords.x && coords.x < region.right
+                && region.top <= coords.y && coords.y < region.bottom {
+            Some(Coords { x: coords.x - region.left, y: coords.y - region.top })
+        } else { None }
+    }
+
+    /// Search the grid and its scrollback for a pattern, replacing any earlier search.
+    pub fn search(&mut self, pattern: &SearchPattern) -> Result<(), regex::Error> {
+        self.search = Some(try!(Search::new(pattern, self.rows_dropped)));
+        self.update_search();
+        self.damage.all();
+        Ok(())
+    }
+
+    pub fn end_search(&mut self) {
+        if self.search.take().is_some() { self.damage.all(); }
+    }
+
+    /// Bring the matches of the search up to date with the contents of the grid. Lines which
+    /// are entirely in the history have been searched already, so only the rest are searched
+    /// again.
+    pub fn update_search(&mut self) {
+        self.search_stale = false;
+        if let Some(mut search) = self.search.take() {
+            let scanned = (0..self.history.len()).rev().find(|&y| {
+                !self.history.get(y).unwrap().is_wrapped()
+            }).map_or(0, |y| y + 1);
+            search.prepare(scanned as u32, self.rows_dropped);
+            let total = self.history.len() + self.grid.height;
+            let mut y = search.scanned as usize;
+            while y < total {
+                let start = y;
+                let mut rows = Vec::new();
+                loop {
+                    let (cells, wrapped) = self.line_row(y);
+                    rows.push(cells);
+                    y += 1;
+                    if !wrapped || y == total { break; }
+                }
+                search.find(start as u32, &rows);
+            }
+            search.scanned = scanned as u32;
+            self.search = Some(search);
+        }
+    }
+
+    // Row y of the history followed by the grid, and whether its line continues on the next row.
+    fn line_row(&self, y: usize) -> (Vec<CharCell>, bool) {
+        match self.history.get(y) {
+            Some(row)   => (row.cells(y as u32, self.grid.width), row.is_wrapped()),
+            None        => {
+                let y = y - self.history.len();
+                let cells = (0..self.grid.width).map(|x| {
+                    self.grid[Coords { x: x as u32, y: y as u32 }].clone()
+                }).collect();
+                (cells, self.grid.is_wrapped(y))
+            }
+        }
+    }
+
+    /// The matches of the search, from the top of the scrollback down. The search is brought up
+    /// to date first if the grid has changed since it was last updated.
+    pub fn search_matches(&mut self) -> &[SearchMatch] {
+        if self.search_stale { self.update_search(); }
+        match self.search {
+            Some(ref search)    => &search.matches[..],
+            None                => &[],
+        }
+    }
+
+    /// The match of the search most recently moved to.
+    pub fn current_match(&self) -> Option<SearchMatch> {
+        self.search.as_ref().and_then(|search| search.current)
+    }
+
+    /// The match of the search covering a cell of the grid, if there is one. The search is
+    /// brought up to date first if the grid has changed since it was last updated.
+    pub fn search_match_at(&mut self, coords: Coords) -> Option<SearchMatch> {
+        if self.search_stale { self.update_search(); }
+        let coords = Coords { x: coords.x, y: coords.y + self.history.len() as u32 };
+        self.search.as_ref().and_then(|search| search.match_at(coords))
+    }
+
+    /// Move to the next match of the search further down, scrolling the view to show it.
+    pub fn next_match(&mut self) -> Option<SearchMatch> {
+        self.step_search(true)
+    }
+
+    /// Move to the next match of the search further up, scrolling the view to show it.
+    pub fn previous_match(&mut self) -> Option<SearchMatch> {
+        self.step_search(false)
+    }
+
+    fn step_search(&mut self, forward: bool) -> Option<SearchMatch> {
+        self.update_search();
+        let found = self.search.as_mut().and_then(|search| search.step(forward));
+        if let Some(m) = found {
+            self.scroll_view_to_row(m.start.y);
+            self.damage.all();
+        }
+        found
+    }
+
+    // Scroll the view as little as possible to show row y, counting from the oldest row of the
+    // history.
+    fn scroll_view_to_row(&mut self, y: u32) {
+        let total = (self.history.len() + self.grid.height) as u32;
+        let bottom = total.saturating_sub(self.view_offset);
+        let top = bottom.saturating_sub(self.visible_height);
+        if y < top {
+            let offset = total.saturating_sub(y + self.visible_height);
+            self.set_view_offset(offset);
+        } else if y >= bottom {
+            self.set_view_offset(total.saturating_sub(y + 1));
+        }
+    }
+
+    /// Start a new selection at a cell of the grid.
+    pub fn start_selection(&mut self, coords: Coords, mode: SelectionMode) {
+        let coords = self.scrollback_coords(coords);
+        self.selection = Some(Selection::new(mode, coords));
+        self.update_selection();
+    }
+
+    /// Move the end of the selection opposite to where it was started to a cell of the grid.
+    pub fn extend_selection(&mut self, coords: Coords) {
+        let coords = self.scrollback_coords(coords);
+        if let Some(ref mut selection) = self.selection { selection.head = coords; }
+        self.update_selection();
+    }
+
+    pub fn clear_selection(&mut self) {
+        if self.selection.take().is_some() { self.damage.all(); }
+    }
+
+    /// The selection, with rows counted from the oldest row of the history.
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    /// Whether a cell of the grid is selected. The extension cells of a wide character are
+    /// selected along with it.
+    pub fn is_selected(&self, coords: Coords) -> bool {
+        if coords.x as usize >= self.grid.width || coords.y as usize >= self.grid.height {
+            return false;
         }
-        self.grid_width = w;
+        let x = match self.grid[coords] {
+            CharCell::Extension(source, _) if source.y == coords.y  => source.x,
+            _                                                       => coords.x,
+        };
+        let coords = Coords { x: x, y: coords.y + self.history.len() as u32 };
+        self.selection.as_ref().map_or(false, |selection| selection.contains(coords))
+    }
+
+    /// Set the characters which separate words in word selections.
+    pub fn set_word_separators(&mut self, separators: String) {
+        self.word_separators = separators;
+    }
+
+    fn update_selection(&mut self) {
+        if let Some(mut selection) = self.selection.take() {
+            selection.update(|y| self.line_row(y as usize).0, &self.word_separators);
+            self.selection = Some(selection);
+        }
+        self.damage.all();
+    }
+
+    // A cell of the grid, within its bounds, with its row counted from the oldest row of the
+    // history.
+    fn scrollback_coords(&self, coords: Coords) -> Coords {
+        let x = cmp::min(coords.x as usize, self.grid.width - 1);
+        let y = cmp::min(coords.y as usize, self.grid.height - 1) + self.history.len();
+        Coords { x: x as u32, y: y as u32 }
+    }
+
+    /// The text of the cells from `start` to `end`, counting rows from the oldest row of the
+    /// history. Soft-wrapped rows are joined, and blanks at the end of each line are trimmed.
+    pub fn text(&self, start: Coords, end: Coords, format: TextFormat) -> String {
+        self.extract(start, end, false, format)
+    }
+
+    /// The text of a rectangle of cells from its top left corner to its bottom right corner,
+    /// with each row on a line of its own.
+    pub fn block_text(&self, start: Coords, end: Coords, format: TextFormat) -> String {
+        self.extract(start, end, true, format)
+    }
+
+    /// The text of the selection, if there is one.
+    pub fn selection_text(&self, format: TextFormat) -> Option<String> {
+        self.selection.map(|selection| {
+            let (start, end) = selection.bounds();
+            self.extract(start, end, selection.mode == SelectionMode::Block, format)
+        })
+    }
+
+    /// The text of the history and the grid, leaving out the blank rows at the bottom.
+    pub fn history_text(&self, format: TextFormat) -> String {
+        let total = self.history.len() + self.grid.height;
+        let last = (0..total).rev().find(|&y| {
+            !self.line_row(y).0.iter().all(is_blank)
+        }).unwrap_or(0);
+        let end = Coords { x: self.grid.width as u32 - 1, y: last as u32 };
+        self.extract(Coords { x: 0, y: 0 }, end, false, format)
+    }
+
+    fn extract(&self, start: Coords, end: Coords, block: bool, format: TextFormat) -> String {
+        let mut writer = TextWriter::new(format);
+        let last = cmp::min(end.y as usize, self.history.len() + self.grid.height - 1);
+        for y in start.y as usize..last + 1 {
+            let (cells, wrapped) = self.line_row(y);
+            let from = if block || y == start.y as usize { start.x as usize } else { 0 };
+            let to = if block || y == end.y as usize { end.x as usize + 1 } else { cells.len() };
+            let to = cmp::min(to, cells.len());
+            let cells = &cells[cmp::min(from, to)..to];
+            let joined = !block && wrapped && y < last;
+            let len = if joined { cells.len() } else {
+                cells.iter().rposition(|cell| !is_blank(cell)).map_or(0, |n| n + 1)
+            };
+            for cell in &cells[..len] { writer.push_cell(cell); }
+            if y < last && !joined { writer.newline(); }
+        }
+        writer.finish()
+    }
+
+    /// The leftmost visible column of a grid which scrolls horizontally.
+    pub fn scroll_x(&self) -> u32 {
+        self.scroll_x
+    }
+
+    /// Pan the visible columns of a grid which scrolls horizontally to begin at column `x`.
+    pub fn set_scroll_x(&mut self, x: u32) {
+        let x = cmp::min(x, (self.grid.width as u32).saturating_sub(self.visible_width));
+        if x != self.scroll_x {
+            self.scroll_x = x;
+            self.damage.all();
+        }
+    }
+
+    // Pan the visible columns so that the cursor is among them.
+    fn follow_cursor(&mut self) {
+        self.grid_width = self.grid.width as u32;
+        let x = self.cursor.coords.x;
+        let scroll_x = if x < self.scroll_x {
+            x
+        } else if x >= self.scroll_x + self.visible_width {
+            x + 1 - self.visible_width
+        } else { self.scroll_x };
+        self.set_scroll_x(scroll_x);
+    }
+
+    // Re-wrap every logical line - a run of rows joined by soft wraps - to a new width, keeping
+    // the cursor, tooltips and wide characters attached to the same cells. Only the rows of a
+    // line which continues from the history into the grid are restored to the grid; the rest of
+    // the history is re-wrapped as compact rows, one line at a time.
+    fn reflow(&mut self, width: usize) {
+        let n = (0..self.history.len()).rev().take_while(|&y| {
+            self.history.get(y).map_or(false, CompactRow::is_wrapped)
+        }).count();
+        self.restore_scrollback(n);
+        self.history.reflow(self.grid.width, width);
+        if let Some(ref mut search) = self.search { search.reset(); }
+        self.search_stale = true;
+        self.selection = None;
+        let old_width = self.grid.width;
+        let min_height = self.grid.height;
+        let max_height = self.grid.max_height();
+        let cursor = self.cursor.coords;
+        let mut data = Vec::with_capacity(min_height * width);
+        let mut wrapped = Vec::with_capacity(min_height);
+        let mut moved = HashMap::new();
+
+        let mut y = 0;
+        while y < self.grid.height {
+            let start = y;
+            while y + 1 < self.grid.height && self.grid.is_wrapped(y) { y += 1; }
+            y += 1;
+            let line: Vec<Coords> = (start..y).flat_map(|y| (0..old_width).map(move |x| {
+                Coords { x: x as u32, y: y as u32 }
+            })).collect();
+            // Blank cells at the end of the line are dropped, unless the cursor is on them.
+            let len = line.iter().rposition(|&coords| {
+                coords == cursor || !self.grid[coords].is_empty()
+            }).map_or(0, |n| n + 1);
+
+            let top = wrapped.len() as u32;
+            let positions = rewrap(&line, len, width, |coords| &self.grid[coords]);
+            let rows = positions.last().map_or(1, |coords| coords.y as usize + 1);
+            data.extend((0..rows * width).map(|_| CharCell::default()));
+            wrapped.extend((0..rows).map(|y| y + 1 < rows));
+            for (&coords, &new) in line.iter().zip(&positions) {
+                let new = Coords { x: new.x, y: new.y + top };
+                data[new.y as usize * width + new.x as usize] = self.grid[coords].clone();
+                moved.insert(coords, new);
+            }
+        }
+
+        // If there are more rows than the grid can hold, blank rows below the cursor go first,
+        // then rows from the top. If there are fewer, the grid is filled out with blank rows.
+        let cursor_row = moved.get(&cursor).map_or(0, |coords| coords.y as usize);
+        while wrapped.len() > max_height && wrapped.len() > cursor_row + 1
+                && data[data.len() - width..].iter().all(CharCell::is_empty) {
+            wrapped.pop();
+            let len = data.len() - width;
+            data.truncate(len);
+        }
+        let dropped = wrapped.len().saturating_sub(max_height);
+        data.drain(..dropped * width);
+        wrapped.drain(..dropped);
+        while wrapped.len() < min_height {
+            wrapped.push(false);
+            data.extend((0..width).map(|_| CharCell::default()));
+        }
+
+        let relocate = |coords: Coords| moved.get(&coords).and_then(|new: &Coords| {
+            if new.y as usize >= dropped {
+                Some(Coords { x: new.x, y: new.y - dropped as u32 })
+            } else { None }
+        });
+        for cell in &mut data {
+            let relocated = match *cell {
+                CharCell::Extension(ref mut src, _)     => relocate(*src).map(|new| *src = new),
+                CharCell::Image { ref mut end, .. }     => relocate(*end).map(|new| *end = new),
+                _                                       => continue,
+            };
+            if relocated.is_none() { cell.empty(); }
+        }
+        self.tooltips = self.tooltips.drain().filter_map(|(coords, tooltip)| {
+            relocate(coords).map(|coords| (coords, tooltip))
+        }).collect();
+        self.links = self.links.drain().filter_map(|(coords, uri)| {
+            relocate(coords).map(|coords| (coords, uri))
+        }).collect();
+        self.cursor.coords = relocate(cursor).unwrap_or(Coords { x: 0, y: 0 });
+        self.wrapped_from = None;
+        self.grid.reflow(width, data, wrapped);
+        self.grid_width = self.grid.width as u32;
+        self.grid_height = self.grid.height as u32;
+        self.damage.all();
+        self.compact_scrollback();
     }
 
     pub fn write(&mut self, data: CellData) {
+        self.set_view_offset(0);
+        self.search_stale = true;
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
         match data {
             CellData::Char(c)       => {
-                let width = c.width().unwrap() as u32;
+                self.continue_line();
+                let coords = self.cursor.coords;
+                self.link_cell(coords);
+                let width = c.width().unwrap_or(1) as u32;
                 self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style);
                 let bounds = self.grid.bounds();
                 let mut coords = self.cursor.coords;
@@ -106,9 +729,12 @@ impl CharGrid {
                     self.grid[coords] = CharCell::Extension(self.cursor.coords,
                                                             self.cursor.text_style);
                 }
-                self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                self.advance();
             }
             CellData::Grapheme(c)   => {
+                self.continue_line();
+                let coords = self.cursor.coords;
+                self.link_cell(coords);
                 let width = c.width() as u32;
                 self.grid[self.cursor.coords] = CharCell::grapheme(c, self.cursor.text_style);
                 let bounds = self.grid.bounds();
@@ -119,17 +745,18 @@ impl CharGrid {
                     self.grid[coords] = CharCell::Extension(self.cursor.coords,
                                                             self.cursor.text_style);
                 }
-                self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                self.advance();
             }
             CellData::ExtensionChar(c)  => {
-                self.cursor.navigate(&mut self.grid, To(Left, 1, true));
+                self.navigate(To(Left, 1, true));
                 if !self.grid[self.cursor.coords].extend_by(c) {
-                    self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                    self.navigate(To(Right, 1, true));
                     self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style);
-                    self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                    self.navigate(To(Right, 1, true));
                 }
             }
             CellData::Image { pos, width, height, data }   => {
+                self.wrapped_from = None;
                 let mut end = self.cursor.coords;
                 end = move_within(end, To(Right, width, false), self.grid.bounds());
                 end = move_within(end, To(Down, height, false), self.grid.bounds());
@@ -140,36 +767,210 @@ impl CharGrid {
                     for coords in iter {
                         self.grid[coords] = CharCell::Extension(cu_coords, self.cursor.text_style);
                     }
-                    self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                    for y in cu_coords.y..end.y + 1 { self.damage_row(y); }
+                    self.navigate(To(Right, 1, true));
                 }
             }
         }
         self.grid_height = self.grid.height as u32;
+        self.compact_scrollback();
+        self.follow_cursor();
     }
 
-    pub fn move_cursor(&mut self, movement: Movement) {
+    // Move past a character just written, noting the row if this wraps onto the next one.
+    // In a grid which scrolls horizontally the line is extended instead, until the grid is as
+    // wide as it may be.
+    fn advance(&mut self) {
+        let y = self.cursor.coords.y;
+        let movement = if self.grid.can_extend_x() {
+            IndexTo(Right, 1)
+        } else {
+            To(Right, 1, true)
+        };
+        self.navigate(movement);
+        if self.cursor.coords.y > y { self.wrapped_from = Some(y); }
+    }
+
+    // Move the cursor, noting the damage to the rows it leaves and enters, and to the view if
+    // the grid scrolls to make room for the movement.
+    fn navigate(&mut self, movement: Movement) {
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
+        if let Some((dir, n)) = self.cursor.scroll_needed(&self.grid, movement) {
+            self.damage_scroll(dir, n);
+            self.search_stale = true;
+        }
         self.cursor.navigate(&mut self.grid, movement);
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
+    }
+
+    // A row only counts as soft-wrapped once text is written at the start of the next row
+    // without the cursor being moved in between.
+    fn continue_line(&mut self) {
+        if let Some(y) = self.wrapped_from.take() {
+            if self.cursor.coords == (Coords { x: 0, y: y + 1 }) {
+                self.grid.set_wrapped(y as usize, true);
+            }
+        }
+    }
+
+    pub fn move_cursor(&mut self, movement: Movement) {
+        self.wrapped_from = None;
+        self.set_view_offset(0);
+        self.navigate(movement);
         self.grid_height = self.grid.height as u32;
+        self.compact_scrollback();
+        self.follow_cursor();
     }
 
     pub fn add_tooltip(&mut self, coords: Coords, tooltip: String) {
         self.tooltips.insert(coords, Tooltip::Basic(tooltip));
+        self.damage_row(coords.y);
     }
 
     pub fn remove_tooltip(&mut self, coords: Coords) {
         self.tooltips.remove(&coords);
+        self.damage_row(coords.y);
     }
 
     pub fn add_drop_down(&mut self, coords: Coords, options: Vec<String>) {
         self.tooltips.insert(coords, Tooltip::Menu { options: options, position: None });
+        self.damage_row(coords.y);
+    }
+
+    /// Set the hyperlink which the characters written from now on are part of, or end it.
+    pub fn set_hyperlink(&mut self, uri: Option<String>) {
+        self.hyperlink = uri;
+    }
+
+    /// The target of the hyperlink on a cell of the grid, including the extension cells of a wide
+    /// character.
+    pub fn hyperlink_at(&self, coords: Coords) -> Option<&str> {
+        if coords.x as usize >= self.grid.width || coords.y as usize >= self.grid.height {
+            return None;
+        }
+        let coords = match self.grid[coords] {
+            CharCell::Extension(source, _)  => source,
+            _                               => coords,
+        };
+        self.links.get(&coords).map(|uri| &uri[..])
+    }
+
+    // Attach the current hyperlink to a cell as it is written over, or detach an old one.
+    fn link_cell(&mut self, coords: Coords) {
+        match self.hyperlink {
+            Some(ref uri)   => { self.links.insert(coords, uri.clone()); }
+            None            => { self.links.remove(&coords); }
+        }
+    }
+
+    // Detach the hyperlinks from the cells in an area which have been erased.
+    fn unlink_area(&mut self, area: Area) {
+        for coords in CoordsIter::from_area(area, self.cursor.coords, self.grid.bounds()) {
+            if self.grid[coords].is_empty() { self.links.remove(&coords); }
+        }
     }
 
     pub fn scroll(&mut self, dir: Direction, n: u32) {
+        self.damage_scroll(dir, n);
+        self.search_stale = true;
         self.grid.scroll(n as usize, dir)
     }
 
+    /// Take the damage done to the view since damage was last taken.
+    pub fn take_damage(&mut self) -> Damage {
+        self.damage.take()
+    }
+
+    /// Mark the whole view as damaged, so that it is drawn again from scratch.
+    pub fn damage_all(&mut self) {
+        self.damage.all();
+    }
+
+    // Note the damage to row y of the grid, if it is in view.
+    fn damage_row(&mut self, y: u32) {
+        let region = self.view_region();
+        if region.top <= y && y < region.bottom { self.damage.row(y - region.top); }
+    }
+
+    // Scrolling down moves the contents of the view up, unless the view is scrolled back.
+    fn damage_scroll(&mut self, dir: Direction, n: u32) {
+        match dir {
+            Down if self.view_offset == 0   => self.damage.scroll(n),
+            _                               => self.damage.all(),
+        }
+    }
+
     pub fn erase(&mut self, area: Area) {
-        self.in_area(area, |grid, coords| grid[coords].empty());
+        self.in_area(area, |grid, coords| {
+            grid[coords].empty();
+            if coords.x as usize + 1 == grid.width { grid.set_wrapped(coords.y as usize, false); }
+        });
+        self.unlink_area(area);
+        self.search_stale = true;
+    }
+
+    pub fn selective_erase(&mut self, area: Area) {
+        self.in_area(area, |grid, coords| {
+            if !grid[coords].style().protected { grid[coords].empty(); }
+        });
+        self.unlink_area(area);
+        self.search_stale = true;
+    }
+
+    /// Drop the scrollback above the visible rows.
+    pub fn truncate_scrollback(&mut self) {
+        let dropped = self.history.len();
+        self.history.clear();
+        let n = self.grid.truncate_scrollback(self.visible_height as usize) as u32;
+        self.count_dropped(dropped + n as usize);
+        self.rows_removed_from_top(n);
+        self.grid_height = self.grid.height as u32;
+        self.search_stale = true;
+        let offset = cmp::min(self.view_offset, self.scrollback_height());
+        self.set_view_offset(offset);
+    }
+
+    // Move everything which refers to a position in the grid up after n rows have been removed
+    // from the top of it. Cells which refer to a removed row are blanked.
+    fn rows_removed_from_top(&mut self, n: u32) {
+        if n == 0 { return; }
+        let up = |coords: Coords| if coords.y >= n {
+            Some(Coords { x: coords.x, y: coords.y - n })
+        } else { None };
+        for cell in &mut self.grid {
+            let moved = match *cell {
+                CharCell::Extension(ref mut source, _)  => up(*source).map(|new| *source = new),
+                CharCell::Image { ref mut end, .. }     => up(*end).map(|new| *end = new),
+                _                                       => continue,
+            };
+            if moved.is_none() { cell.empty(); }
+        }
+        self.tooltips = self.tooltips.drain().filter(|&(coords, _)| coords.y >= n).map(|(c, t)| {
+            (Coords { x: c.x, y: c.y - n }, t)
+        }).collect();
+        self.links = self.links.drain().filter(|&(coords, _)| coords.y >= n).map(|(c, uri)| {
+            (Coords { x: c.x, y: c.y - n }, uri)
+        }).collect();
+        self.cursor.coords.y = self.cursor.coords.y.saturating_sub(n);
+    }
+
+    // Keep cells, tooltips and the cursor pointing at the same rows after n rows are added to
+    // the top of the grid.
+    fn rows_added_to_top(&mut self, n: u32) {
+        for cell in &mut self.grid {
+            match *cell {
+                CharCell::Extension(ref mut source, _)  => source.y += n,
+                CharCell::Image { ref mut end, .. }     => end.y += n,
+                _                                       => (),
+            }
+        }
+        self.tooltips = self.tooltips.drain().map(|(c, t)| (Coords { x: c.x, y: c.y + n }, t))
+                                     .collect();
+        self.links = self.links.drain().map(|(c, uri)| (Coords { x: c.x, y: c.y + n }, uri))
+                               .collect();
+        self.cursor.coords.y += n;
     }
 
     pub fn insert_blank_at(&mut self, n: u32) {
@@ -180,14 +981,21 @@ impl CharGrid {
         for coords in iter.rev().skip(n as usize) {
             self.grid.moveover(coords, Coords {x: coords.x + n, y: coords.y});
         }
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
+        self.search_stale = true;
     }
 
     pub fn remove_at(&mut self, n: u32) {
         self.in_area(CursorTo(ToEdge(Right)), |grid, coords| {
-            if coords.x + n < grid.width as u32 {
-                grid.moveover(Coords {x: coords.x + n, y: coords.y}, coords);
+            match coords.x.checked_add(n) {
+                Some(x) if x < grid.width as u32    => {
+                    grid.moveover(Coords {x: x, y: coords.y}, coords);
+                }
+                _                                   => grid[coords] = CharCell::default(),
             }
-        })
+        });
+        self.search_stale = true;
     }
 
     pub fn insert_rows_at(&mut self, n: u32, include: bool) {
@@ -201,14 +1009,32 @@ impl CharGrid {
         for coords in CoordsIter::from_region(region).rev().skip(n as usize * self.grid.width) {
             self.grid.moveover(coords, Coords {x: coords.x, y: coords.y + n});
         }
+        for y in (region.top as usize..self.grid.height).rev() {
+            let wrapped = y >= region.top as usize + n as usize
+                            && self.grid.is_wrapped(y - n as usize);
+            self.grid.set_wrapped(y, wrapped);
+            self.damage_row(y as u32);
+        }
+        self.search_stale = true;
     }
 
     pub fn remove_rows_at(&mut self, n: u32, include: bool) {
         self.in_area(BelowCursor(include), |grid, coords| {
-            if coords.y + n < grid.height as u32 {
-                grid.moveover(Coords {x: coords.x, y: coords.y + n}, coords);
+            match coords.y.checked_add(n) {
+                Some(y) if y < grid.height as u32   => {
+                    grid.moveover(Coords {x: coords.x, y: y}, coords);
+                }
+                _                                   => grid[coords] = CharCell::default(),
             }
-        })
+        });
+        let n = n as usize;
+        let top = self.cursor.coords.y as usize + if include { 0 } else { 1 };
+        for y in top..self.grid.height {
+            let wrapped = y + n < self.grid.height && self.grid.is_wrapped(y + n);
+            self.grid.set_wrapped(y, wrapped);
+            self.damage_row(y as u32);
+        }
+        self.search_stale = true;
     }
 
     pub fn set_style(&mut self, style: Style) {
@@ -216,15 +1042,25 @@ impl CharGrid {
     }
 
     pub fn reset_styles(&mut self) {
+        let protected = self.cursor.text_style.protected;
         self.cursor.text_style = Styles::default();
+        self.cursor.text_style.protected = protected;
+    }
+
+    pub fn set_protected(&mut self, protected: bool) {
+        self.cursor.text_style.protected = protected;
     }
 
     pub fn set_cursor_style(&mut self, style: Style) {
         self.cursor.style.update(style);
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
     }
 
     pub fn reset_cursor_styles(&mut self) {
         self.cursor.style = Styles::default();
+        let y = self.cursor.coords.y;
+        self.damage_row(y);
     }
 
     pub fn set_style_in_area(&mut self, area: Area, style: Style) {
@@ -262,11 +1098,20 @@ impl CharGrid {
     fn in_area<F>(&mut self, area: Area, f: F) where F: Fn(&mut Grid<CharCell>, Coords) {
         for coords in CoordsIter::from_area(area, self.cursor.coords, self.grid.bounds()) {
             f(&mut self.grid, coords);
+            self.damage_row(coords.y);
         }
     }
 
 }
 
+// Whether a cell shows nothing but its background.
+fn is_blank(cell: &CharCell) -> bool {
+    match *cell {
+        CharCell::Empty(_) | CharCell::Char(' ', _) => true,
+        _                                           => false,
+    }
+}
+
 impl<'a> IntoIterator for &'a CharGrid {
     type IntoIter = <&'a Grid<CharCell> as IntoIterator>::IntoIter;
     type Item = &'a CharCell;
@@ -275,13 +1120,47 @@ impl<'a> IntoIterator for &'a CharGrid {
     }
 }
 
+// The new positions of the first `len` cells of a line, given as the coordinates of its cells in
+// order, when it is re-wrapped to `width` columns. Rows are counted from the first row of the
+// line. A wide character is kept together with its extension cells.
+fn rewrap<'a, F>(line: &[Coords], len: usize, width: usize, cell: F) -> Vec<Coords>
+        where F: Fn(Coords) -> &'a CharCell {
+    let mut positions = Vec::with_capacity(len);
+    let (mut x, mut y) = (0, 0);
+    let mut i = 0;
+    while i < len {
+        let span = 1 + line[i+1..len].iter().take_while(|&&coords| match *cell(coords) {
+            CharCell::Extension(source, _)  => source == line[i],
+            _                               => false,
+        }).count();
+        if x + span > width && x > 0 {
+            x = 0;
+            y += 1;
+        }
+        for _ in 0..span {
+            if x == width {
+                x = 0;
+                y += 1;
+            }
+            positions.push(Coords { x: x as u32, y: y });
+            x += 1;
+        }
+        i += span;
+    }
+    positions
+}
+
 #[cfg(test)]
 mod tests {
 
+    use std::usize;
+
     use super::*;
 
+    use image::{DynamicImage, ImageBuffer};
+
     use cfg;
-    use datatypes::{CellData, Coords, Direction, Movement};
+    use datatypes::{Area, CellData, Coords, Direction, MediaPosition, Movement};
 
     fn run_test<F: Fn(CharGrid, u32)>(test: F) {
         test(CharGrid::new(10, 10, false, false), 10);
@@ -448,6 +1327,413 @@ mod tests {
         })
     }
 
+    #[test]
+    fn set_height() {
+        let mut grid = CharGrid::new(10, 10, false, false);
+        setup(&mut grid);
+        grid.move_cursor(Movement::Position(Coords {x:0, y:2}));
+        grid.set_height(5);
+        assert_eq!(grid.grid_height, 5);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "A");
+        grid.set_height(2);
+        assert_eq!(grid.grid_height, 2);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "1");
+        assert_eq!(grid.cursor_position(), Coords {x:0, y:1});
+        grid.set_height(4);
+        assert_eq!(grid.grid_height, 4);
+    }
+
+    #[test]
+    fn set_height_drops_image_rows() {
+        let mut grid = CharGrid::new(4, 4, false, false);
+        grid.move_cursor(Movement::Position(Coords {x:0, y:1}));
+        grid.write(CellData::Image {
+            pos: MediaPosition::default(),
+            width: 2,
+            height: 2,
+            data: DynamicImage::ImageRgb8(ImageBuffer::new(1, 1)),
+        });
+        assert!(grid.grid[Coords {x:0, y:2}].is_char_extension());
+        grid.set_height(2);
+        assert_eq!(grid.grid_height, 2);
+        assert!(grid.grid[Coords {x:0, y:0}].is_empty());
+        assert!(grid.grid[Coords {x:1, y:1}].is_empty());
+    }
+
+    #[test]
+    fn set_height_with_scrollback() {
+        let mut grid = CharGrid::new(10, 10, false, true);
+        setup(&mut grid);
+        grid.move_cursor(Movement::Position(Coords {x:0, y:9}));
+        grid.write(CellData::Char('Z'));
+        grid.set_height(5);
+        assert_eq!(grid.grid_height, 10);
+        assert_eq!(grid.cursor_position(), Coords {x:1, y:9});
+        grid.set_height(12);
+        assert_eq!(grid.grid_height, 12);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "A");
+        grid.set_height(10);
+        assert_eq!(grid.grid_height, 10);
+        assert_eq!(grid.grid[Coords {x:0, y:9}].repr(), "Z");
+    }
+
+    #[test]
+    fn scroll_x() {
+        let mut grid = CharGrid::new(5, 2, true, false);
+        for c in "ABCDEFG".chars() { grid.write(CellData::Char(c)); }
+        assert_eq!(grid.grid_width, 8);
+        assert_eq!(grid.grid[Coords {x:6, y:0}].repr(), "G");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "");
+        assert_eq!(grid.cursor_position(), Coords {x:7, y:0});
+        assert_eq!(grid.scroll_x(), 3);
+        grid.set_scroll_x(0);
+        assert_eq!(grid.scroll_x(), 0);
+        grid.set_scroll_x(10);
+        assert_eq!(grid.scroll_x(), 3);
+        grid.move_cursor(Movement::Column(1));
+        assert_eq!(grid.scroll_x(), 1);
+        grid.set_width(10);
+        assert_eq!(grid.grid_width, 10);
+        assert_eq!(grid.scroll_x(), 0);
+        grid.set_width(4);
+        assert_eq!(grid.grid_width, 10);
+        assert_eq!(grid.grid[Coords {x:6, y:0}].repr(), "G");
+    }
+
+    #[test]
+    fn scroll_x_at_max_width() {
+        let max = cfg::MAX_WIDTH;
+        let mut grid = CharGrid::new(4, 2, true, false);
+        grid.write(CellData::Char('a'));
+        for _ in 1..max { grid.write(CellData::Char('b')); }
+        grid.write(CellData::Char('c'));
+        assert_eq!(grid.grid_width, max);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "a");
+        assert_eq!(grid.grid[Coords {x:max - 1, y:0}].repr(), "b");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "c");
+        assert!(grid.grid.is_wrapped(0));
+    }
+
+    #[test]
+    fn set_max_width() {
+        let mut grid = CharGrid::new(4, 2, true, false);
+        grid.set_max_width(6);
+        for c in "abcdefg".chars() { grid.write(CellData::Char(c)); }
+        assert_eq!(grid.grid_width, 6);
+        assert_eq!(grid.grid[Coords {x:5, y:0}].repr(), "f");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "g");
+        let restored = CharGrid::from_state(&grid.state().unwrap()).unwrap();
+        assert_eq!(restored.grid.max_width(), 6);
+    }
+
+    #[test]
+    fn scrollback_rows() {
+        let mut grid = CharGrid::with_scrollback(10, 5, false, Some(Scrollback::Rows(8)));
+        setup(&mut grid);
+        grid.move_cursor(Movement::ToEdge(Direction::Down));
+        grid.move_cursor(Movement::NextLine(3));
+        assert_eq!(grid.grid_height, 8);
+        grid.set_scrollback(Scrollback::Rows(6));
+        assert_eq!(grid.grid_height, 6);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "!");
+        assert_eq!(grid.cursor_position(), Coords {x:0, y:5});
+    }
+
+    #[test]
+    fn scrollback_history() {
+        let scrollback = Scrollback::Unlimited { max_bytes: usize::MAX };
+        let mut grid = CharGrid::with_scrollback(5, 2, false, Some(scrollback));
+        for c in "ABCDEFGH".chars() {
+            grid.write(CellData::Char(c));
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.grid_height, 4);
+        assert_eq!(grid.scrollback_len(), 5);
+        assert_eq!(grid.scrollback_row(0).unwrap()[0].repr(), "A");
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "F");
+        assert_eq!(grid.cursor_position(), Coords {x:0, y:3});
+        assert_eq!(grid.restore_scrollback(2), 2);
+        assert_eq!(grid.grid_height, 6);
+        assert_eq!(grid.scrollback_len(), 3);
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "D");
+        assert_eq!(grid.cursor_position(), Coords {x:0, y:5});
+        grid.set_scrollback(Scrollback::Rows(6));
+        assert_eq!(grid.grid_height, 4);
+        assert_eq!(grid.scrollback_len(), 2);
+        assert_eq!(grid.scrollback_row(0).unwrap()[0].repr(), "D");
+        grid.set_scrollback(Scrollback::Unlimited { max_bytes: 0 });
+        assert_eq!(grid.scrollback_len(), 0);
+        assert_eq!(grid.scrollback_bytes(), 0);
+    }
+
+    #[test]
+    fn scrollback_budget() {
+        let scrollback = Scrollback::Unlimited { max_bytes: usize::MAX };
+        let mut grid = CharGrid::with_scrollback(5, 2, false, Some(scrollback));
+        for c in "ABCDEFGH".chars() {
+            grid.write(CellData::Char(c));
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.scrollback_len(), 5);
+        let row = grid.scrollback_bytes() / 5;
+        grid.set_scrollback(Scrollback::Unlimited { max_bytes: row * 3 });
+        assert_eq!(grid.scrollback_len(), 3);
+        assert_eq!(grid.scrollback_bytes(), row * 3);
+        assert_eq!(grid.scrollback_row(0).unwrap()[0].repr(), "C");
+        for c in "IJ".chars() {
+            grid.write(CellData::Char(c));
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.scrollback_len(), 3);
+        assert_eq!(grid.scrollback_row(0).unwrap()[0].repr(), "E");
+        assert_eq!(grid.grid_height, 4);
+    }
+
+    #[test]
+    fn scroll_view() {
+        let mut grid = CharGrid::new(5, 2, false, true);
+        for c in "ABCDEFGH".chars() {
+            grid.write(CellData::Char(c));
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.scrollback_height(), 7);
+        assert_eq!(grid.view().count(), 10);
+        assert_eq!(grid.view().next().unwrap().repr(), "H");
+        grid.scroll_view(Direction::Up, 1);
+        assert_eq!(grid.view().next().unwrap().repr(), "G");
+        assert_eq!(grid.cursor_in_view(), None);
+        grid.scroll_view_pages(Direction::Up, 1);
+        assert_eq!(grid.view_offset(), 3);
+        assert_eq!(grid.scrollback_len(), 4);
+        assert_eq!(grid.view().next().unwrap().repr(), "E");
+        grid.scroll_view_to_top();
+        assert_eq!(grid.view_offset(), 7);
+        assert_eq!(grid.view().next().unwrap().repr(), "A");
+        grid.scroll_view(Direction::Down, 100);
+        assert_eq!(grid.view_offset(), 0);
+        grid.scroll_view(Direction::Up, 2);
+        grid.write(CellData::Char('I'));
+        assert_eq!(grid.view_offset(), 0);
+        assert_eq!(grid.scrollback_len(), 5);
+        assert_eq!(grid.cursor_in_view(), Some(Coords {x:1, y:1}));
+    }
+
+    #[test]
+    fn damage() {
+        let mut grid = CharGrid::new(5, 3, false, false);
+        assert!(grid.take_damage().all);
+        grid.write(CellData::Char('A'));
+        assert_eq!(grid.take_damage().rows, [0]);
+        grid.move_cursor(Movement::Position(Coords {x:0, y:2}));
+        assert_eq!(grid.take_damage().rows, [0, 2]);
+        grid.move_cursor(Movement::NextLine(1));
+        let damage = grid.take_damage();
+        assert_eq!(damage.scrolled, 1);
+        assert_eq!(damage.rows, [1, 2]);
+        assert!(grid.take_damage().is_empty());
+        grid.start_selection(Coords {x:0, y:0}, SelectionMode::Line);
+        assert!(grid.take_damage().all);
+    }
+
+    #[test]
+    fn hyperlink() {
+        let mut grid = CharGrid::new(5, 2, false, false);
+        grid.set_hyperlink(Some(String::from("http://example.com")));
+        grid.write(CellData::Char('A'));
+        grid.set_hyperlink(None);
+        grid.write(CellData::Char('B'));
+        assert_eq!(grid.hyperlink_at(Coords {x:0, y:0}), Some("http://example.com"));
+        assert_eq!(grid.hyperlink_at(Coords {x:1, y:0}), None);
+        grid.move_cursor(Movement::Position(Coords {x:0, y:0}));
+        grid.write(CellData::Char('C'));
+        assert_eq!(grid.hyperlink_at(Coords {x:0, y:0}), None);
+    }
+
+    #[test]
+    fn hyperlinks_and_tooltips() {
+        let mut grid = CharGrid::new(5, 2, false, false);
+        grid.add_tooltip(Coords {x:0, y:0}, String::from("tip"));
+        grid.set_hyperlink(Some(String::from("http://example.com")));
+        grid.write(CellData::Char('A'));
+        grid.write(CellData::Char('B'));
+        assert_eq!(grid.tooltip_at(Coords {x:0, y:0}), Some(&Tooltip::Basic(String::from("tip"))));
+        assert_eq!(grid.tooltip_at(Coords {x:1, y:0}), None);
+        assert_eq!(grid.hyperlink_at(Coords {x:0, y:0}), Some("http://example.com"));
+        grid.set_hyperlink(None);
+        grid.move_cursor(Movement::Position(Coords {x:1, y:0}));
+        grid.erase(Area::CursorTo(Movement::ToEdge(Direction::Right)));
+        assert_eq!(grid.hyperlink_at(Coords {x:0, y:0}), Some("http://example.com"));
+        assert_eq!(grid.hyperlink_at(Coords {x:1, y:0}), None);
+        assert!(grid.tooltip_at(Coords {x:0, y:0}).is_some());
+    }
+
+    #[test]
+    fn search() {
+        let mut grid = CharGrid::new(5, 2, false, true);
+        for word in &["foo", "bar", "foo", "baz", "foo"] {
+            for c in word.chars() { grid.write(CellData::Char(c)); }
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.scrollback_len(), 2);
+        grid.search(&SearchPattern::Literal(String::from("foo"))).unwrap();
+        assert_eq!(grid.search_matches().len(), 3);
+        assert_eq!(grid.previous_match().unwrap().start, Coords {x:0, y:4});
+        assert_eq!(grid.view_offset(), 0);
+        assert_eq!(grid.previous_match().unwrap().start, Coords {x:0, y:2});
+        assert_eq!(grid.view_offset(), 2);
+        assert_eq!(grid.previous_match().unwrap().start, Coords {x:0, y:0});
+        assert_eq!(grid.view_offset(), 4);
+        assert!(grid.search_match_at(Coords {x:2, y:0}).is_some());
+        assert!(grid.search_match_at(Coords {x:3, y:0}).is_none());
+        for c in "foo".chars() { grid.write(CellData::Char(c)); }
+        assert_eq!(grid.search_matches().len(), 4);
+        assert_eq!(grid.next_match().unwrap().start, Coords {x:0, y:2});
+        grid.erase(Area::CursorRow);
+        assert_eq!(grid.search_matches().len(), 3);
+        assert!(grid.search(&SearchPattern::Regex(String::from("("))).is_err());
+    }
+
+    #[test]
+    fn selection() {
+        let mut grid = CharGrid::with_scrollback(5, 2, false, Some(Scrollback::Rows(5)));
+        for word in &["ab c", "ef", "gh"] {
+            for c in word.chars() { grid.write(CellData::Char(c)); }
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        grid.start_selection(Coords {x:3, y:0}, SelectionMode::Word);
+        assert!(grid.is_selected(Coords {x:3, y:0}));
+        grid.extend_selection(Coords {x:0, y:1});
+        assert_eq!(grid.selection().unwrap().bounds(), (Coords {x:3, y:0}, Coords {x:1, y:1}));
+        assert!(grid.is_selected(Coords {x:4, y:0}));
+        assert!(!grid.is_selected(Coords {x:2, y:1}));
+        for word in &["ij", "kl"] {
+            for c in word.chars() { grid.write(CellData::Char(c)); }
+            grid.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(grid.scrollback_len(), 1);
+        assert_eq!(grid.selection().unwrap().bounds(), (Coords {x:0, y:0}, Coords {x:1, y:0}));
+        assert!(!grid.is_selected(Coords {x:0, y:0}));
+        for c in "mn".chars() { grid.write(CellData::Char(c)); }
+        grid.move_cursor(Movement::NextLine(1));
+        assert!(grid.selection().is_none());
+    }
+
+    #[test]
+    fn text() {
+        use datatypes::Style;
+
+        let mut grid = CharGrid::new(5, 3, false, false);
+        for c in "ABCDEFG".chars() { grid.write(CellData::Char(c)); }
+        grid.move_cursor(Movement::NextLine(1));
+        grid.set_style(Style::Bold(true));
+        for c in "hi  ".chars() { grid.write(CellData::Char(c)); }
+        assert_eq!(grid.history_text(TextFormat::Plain), "ABCDEFG\nhi");
+        assert_eq!(grid.text(Coords {x:3, y:0}, Coords {x:0, y:1}, TextFormat::Plain), "DEF");
+        assert_eq!(grid.block_text(Coords {x:1, y:0}, Coords {x:2, y:1}, TextFormat::Plain),
+                   "BC\nG");
+        assert_eq!(grid.text(Coords {x:0, y:1}, Coords {x:4, y:2}, TextFormat::Ansi),
+                   "FG\n\x1b[0;1mhi\x1b[0m");
+        grid.start_selection(Coords {x:1, y:2}, SelectionMode::Line);
+        assert_eq!(grid.selection_text(TextFormat::Plain).unwrap(), "hi");
+    }
+
+    #[test]
+    fn reflow() {
+        let mut grid = CharGrid::new(10, 10, false, false);
+        for c in "ABCDEFGHIJKLM".chars() { grid.write(CellData::Char(c)); }
+        grid.move_cursor(Movement::NextLine(1));
+        for c in "xyz".chars() { grid.write(CellData::Char(c)); }
+        assert!(grid.grid.is_wrapped(0));
+        assert!(!grid.grid.is_wrapped(1));
+        grid.set_width(5);
+        assert_eq!(grid.grid[Coords {x:4, y:0}].repr(), "E");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "F");
+        assert_eq!(grid.grid[Coords {x:2, y:2}].repr(), "M");
+        assert_eq!(grid.grid[Coords {x:0, y:3}].repr(), "x");
+        assert!(grid.grid.is_wrapped(0) && grid.grid.is_wrapped(1) && !grid.grid.is_wrapped(2));
+        assert_eq!(grid.cursor_position(), Coords {x:3, y:3});
+        assert_eq!(grid.grid_height, 10);
+        grid.set_width(20);
+        assert_eq!(grid.grid[Coords {x:12, y:0}].repr(), "M");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "x");
+        assert!(!grid.grid.is_wrapped(0));
+        assert_eq!(grid.cursor_position(), Coords {x:3, y:1});
+        assert_eq!(grid.grid_height, 10);
+    }
+
+    #[test]
+    fn reflow_scrollback() {
+        let scrollback = Scrollback::Unlimited { max_bytes: usize::MAX };
+        let mut grid = CharGrid::with_scrollback(4, 2, false, Some(scrollback));
+        for (i, line) in ["ABCDEF", "xy", "123456789", "a", "b"].iter().enumerate() {
+            if i > 0 { grid.move_cursor(Movement::NextLine(1)); }
+            for c in line.chars() { grid.write(CellData::Char(c)); }
+        }
+        assert_eq!(grid.scrollback_len(), 4);
+        let text = grid.history_text(TextFormat::Plain);
+        assert_eq!(text, "ABCDEF\nxy\n123456789\na\nb");
+        grid.set_width(6);
+        assert_eq!(grid.history_text(TextFormat::Plain), text);
+        assert!(grid.scrollback_len() >= 2);
+        assert_eq!(grid.scrollback_row(0).unwrap()[5].repr(), "F");
+        assert_eq!(grid.scrollback_row(1).unwrap()[0].repr(), "x");
+        assert_eq!(grid.cursor_position().x, 1);
+    }
+
+    #[test]
+    fn reflow_wide_chars() {
+        let mut grid = CharGrid::new(5, 5, false, false);
+        for c in "AB\u{4e00}CD".chars() { grid.write(CellData::Char(c)); }
+        grid.set_width(3);
+        assert_eq!(grid.grid[Coords {x:2, y:0}].repr(), "");
+        assert_eq!(grid.grid[Coords {x:0, y:1}].repr(), "\u{4e00}");
+        assert_eq!(grid.grid[Coords {x:1, y:1}].repr(), "EXT");
+        assert_eq!(grid.grid[Coords {x:2, y:1}].repr(), "C");
+        assert_eq!(grid.grid[Coords {x:0, y:2}].repr(), "D");
+        match grid.grid[Coords {x:1, y:1}] {
+            CharCell::Extension(source, _)  => assert_eq!(source, Coords {x:0, y:1}),
+            _                               => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn selective_erase() {
+        run_test(|mut grid, _| {
+            grid.write(CellData::Char('A'));
+            grid.set_protected(true);
+            grid.write(CellData::Char('B'));
+            grid.reset_styles();
+            grid.write(CellData::Char('C'));
+            grid.set_protected(false);
+            grid.write(CellData::Char('D'));
+            grid.move_cursor(Movement::ToBeginning);
+            grid.selective_erase(Area::CursorRow);
+            assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "");
+            assert_eq!(grid.grid[Coords {x:1, y:0}].repr(), "B");
+            assert_eq!(grid.grid[Coords {x:2, y:0}].repr(), "C");
+            assert_eq!(grid.grid[Coords {x:3, y:0}].repr(), "");
+            grid.erase(Area::CursorRow);
+            assert_eq!(grid.grid[Coords {x:1, y:0}].repr(), "");
+        })
+    }
+
+    #[test]
+    fn truncate_scrollback() {
+        let mut grid = CharGrid::new(10, 10, false, true);
+        setup(&mut grid);
+        grid.move_cursor(Movement::ToEdge(Direction::Down));
+        grid.move_cursor(Movement::NextLine(2));
+        grid.add_tooltip(Coords {x:0, y:1}, String::from("gone"));
+        grid.add_tooltip(Coords {x:0, y:5}, String::from("kept"));
+        assert_eq!(grid.grid_height, 12);
+        grid.truncate_scrollback();
+        assert_eq!(grid.grid_height, 10);
+        assert_eq!(grid.cursor_position(), Coords {x:0, y:9});
+        assert_eq!(grid.grid[Coords {x:0, y:0}].repr(), "!");
+        assert!(grid.tooltip_at(Coords {x:0, y:3}).is_some());
+        assert_eq!(grid.tooltips.len(), 1);
+    }
+
     #[test]
     fn remove_rows_at() {
         run_test(|mut grid, _| {
@@ -0,0 +1,385 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+
+use cfg;
+use datatypes::Coords;
+use terminal::{CharCell, Styles};
+
+/// How much history a grid which scrolls vertically keeps.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Scrollback {
+    /// Keep at most this many rows, counting the visible rows.
+    Rows(u32),
+    /// Keep as many rows of compacted history as fit in this many bytes. The oldest rows are
+    /// evicted first.
+    Unlimited { max_bytes: usize },
+}
+
+impl Scrollback {
+    /// The most rows a grid of this height may hold, or `None` if only its byte budget limits it.
+    pub fn max_rows(&self, height: usize) -> Option<usize> {
+        match *self {
+            Scrollback::Rows(n)             => Some(cmp::max(n as usize, height)),
+            Scrollback::Unlimited { .. }    => None,
+        }
+    }
+}
+
+impl Default for Scrollback {
+    fn default() -> Scrollback {
+        Scrollback::Rows(cfg::SCROLLBACK)
+    }
+}
+
+/// Rows which have scrolled out of a grid, oldest first, stored as compact rows.
+#[derive(Default)]
+pub struct History {
+    rows: VecDeque<CompactRow>,
+    bytes: usize,
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The memory used by the rows of this history.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn get(&self, n: usize) -> Option<&CompactRow> {
+        self.rows.get(n)
+    }
+
+    pub fn push_back(&mut self, row: CompactRow) {
+        self.bytes += row.bytes();
+        self.rows.push_back(row);
+    }
+
+    pub fn pop_back(&mut self) -> Option<CompactRow> {
+        self.rows.pop_back().map(|row| {
+            self.bytes -= row.bytes();
+            row
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.clear();
+        self.bytes = 0;
+    }
+
+    /// Re-wrap the lines of this history, whose rows are `old_width` columns wide, to `width`
+    /// columns. Lines are expanded into cells one at a time, and a line held by a single row
+    /// which fits in the new width is kept as it is.
+    pub fn reflow(&mut self, old_width: usize, width: usize) {
+        let mut rows = mem::replace(&mut self.rows, VecDeque::new());
+        self.bytes = 0;
+        let mut line = Vec::new();
+        while let Some(row) = rows.pop_front() {
+            let wrapped = row.is_wrapped();
+            line.push(row);
+            if wrapped && !rows.is_empty() { continue; }
+            if line.len() == 1 && line[0].len() <= width {
+                self.push_back(line.pop().unwrap());
+            } else {
+                for row in reflow_line(&line, old_width, width) { self.push_back(row); }
+                line.clear();
+            }
+        }
+    }
+
+    /// Drop the oldest rows until the history and the `live` rows of its grid fit within the
+    /// scrollback settings, returning the number of rows dropped.
+    pub fn evict(&mut self, scrollback: Scrollback, live: usize) -> usize {
+        let mut n = 0;
+        while let Some(bytes) = self.rows.front().map(CompactRow::bytes) {
+            let fits = match scrollback {
+                Scrollback::Rows(max)                   => self.rows.len() + live <= max as usize,
+                Scrollback::Unlimited { max_bytes }     => self.bytes <= max_bytes,
+            };
+            if fits { break; }
+            self.rows.pop_front();
+            self.bytes -= bytes;
+            n += 1;
+        }
+        n
+    }
+}
+
+/// A row of cells stored as its text and runs of cells which share a style. Blank cells at the
+/// end of the row are not stored.
+#[derive(Clone)]
+pub struct CompactRow {
+    text: String,
+    runs: Vec<Run>,
+    wrapped: bool,
+}
+
+#[derive(Clone)]
+enum Run {
+    // Blank cells.
+    Empty(u32, Styles),
+    // Cells holding one character each, taken in order from the text of the row.
+    Chars(u32, Styles),
+    // A cell holding a grapheme of this many bytes of the text of the row.
+    Grapheme(usize, Styles),
+    // A cell extending the cell in column `x`, `up` rows above this one.
+    Extension { x: u32, up: u32, style: Styles },
+    // A cell holding an image which ends `down` rows below this one.
+    Image(Box<CharCell>, u32),
+}
+
+impl CompactRow {
+    /// Compact the cells of row `y` of a grid.
+    pub fn new(cells: &[CharCell], y: u32, wrapped: bool) -> CompactRow {
+        let len = cells.iter().rposition(|cell| match *cell {
+            CharCell::Empty(style)  => style != Styles::default(),
+            _                       => true,
+        }).map_or(0, |n| n + 1);
+        let mut text = String::new();
+        let mut runs = Vec::new();
+        for cell in &cells[..len] {
+            let run = match *cell {
+                CharCell::Empty(style)              => Run::Empty(1, style),
+                CharCell::Char(c, style)            => { text.push(c); Run::Chars(1, style) }
+                CharCell::Grapheme(ref s, style)    => {
+                    text.push_str(s);
+                    Run::Grapheme(s.len(), style)
+                }
+                CharCell::Extension(source, style)  => Run::Extension {
+                    x: source.x,
+                    up: y.saturating_sub(source.y),
+                    style: style,
+                },
+                CharCell::Image { end, .. }         => {
+                    Run::Image(Box::new(cell.clone()), end.y.saturating_sub(y))
+                }
+            };
+            let merged = match (runs.last_mut(), &run) {
+                (Some(&mut Run::Empty(ref mut n, prev)), &Run::Empty(_, style))
+                    | (Some(&mut Run::Chars(ref mut n, prev)), &Run::Chars(_, style))
+                    if prev == style => { *n += 1; true }
+                _                   => false,
+            };
+            if !merged { runs.push(run); }
+        }
+        text.shrink_to_fit();
+        runs.shrink_to_fit();
+        CompactRow { text: text, runs: runs, wrapped: wrapped }
+    }
+
+    /// Rebuild the cells of this row as row `y` of a grid of this width.
+    pub fn cells(&self, y: u32, width: usize) -> Vec<CharCell> {
+        let mut cells = Vec::with_capacity(width);
+        let mut text = &self.text[..];
+        for run in &self.runs {
+            match *run {
+                Run::Empty(n, style)        => {
+                    cells.extend((0..n).map(|_| CharCell::Empty(style)));
+                }
+                Run::Chars(n, style)        => {
+                    let mut chars = text.chars();
+                    cells.extend(chars.by_ref().take(n as usize).map(|c| {
+                        CharCell::Char(c, style)
+                    }));
+                    text = chars.as_str();
+                }
+                Run::Grapheme(len, style)   => {
+                    cells.push(CharCell::Grapheme(String::from(&text[..len]), style));
+                    text = &text[len..];
+                }
+                Run::Extension { x, up, style } => {
+                    let source = Coords { x: x, y: y.saturating_sub(up) };
+                    cells.push(CharCell::Extension(source, style));
+                }
+                Run::Image(ref cell, down)  => {
+                    let mut cell = (**cell).clone();
+                    if let CharCell::Image { ref mut end, .. } = cell { end.y = y + down; }
+                    cells.push(cell);
+                }
+            }
+        }
+        cells.resize(width, CharCell::default());
+        cells
+    }
+
+    /// The number of cells stored for this row, which leaves out the blank cells at its end.
+    pub fn len(&self) -> usize {
+        self.runs.iter().fold(0, |len, run| match *run {
+            Run::Empty(n, _) | Run::Chars(n, _) => len + n as usize,
+            _                                   => len + 1,
+        })
+    }
+
+    /// Whether the line this row holds continues on the next row.
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+
+    /// An estimate of the memory used by this row.
+    pub fn bytes(&self) -> usize {
+        mem::size_of::<CompactRow>() + self.text.capacity()
+            + self.runs.capacity() * mem::size_of::<Run>()
+    }
+}
+
+// Re-wrap the rows of one line, which are `old_width` columns wide, to `width` columns. Blank
+// cells at the end of the line are dropped, as they are when the grid is reflowed.
+fn reflow_line(line: &[CompactRow], old_width: usize, width: usize) -> Vec<CompactRow> {
+    let cells: Vec<Vec<CharCell>> = line.iter().enumerate().map(|(y, row)| {
+        row.cells(y as u32, old_width)
+    }).collect();
+    let coords: Vec<Coords> = (0..line.len()).flat_map(|y| (0..old_width).map(move |x| {
+        Coords { x: x as u32, y: y as u32 }
+    })).collect();
+    let at = |coords: Coords| &cells[coords.y as usize][coords.x as usize];
+    let len = coords.iter().rposition(|&coords| !at(coords).is_empty()).map_or(0, |n| n + 1);
+    let positions = super::rewrap(&coords, len, width, &at);
+    let moved: HashMap<Coords, Coords> = coords.iter().cloned().zip(positions.iter().cloned())
+                                               .collect();
+
+    let rows = positions.last().map_or(1, |coords| coords.y as usize + 1);
+    let mut data = vec![CharCell::default(); rows * width];
+    for (&old, &new) in coords.iter().zip(&positions) {
+        let mut cell = at(old).clone();
+        let relocated = match cell {
+            CharCell::Extension(ref mut source, _)  => moved.get(source).map(|&n| *source = n),
+            // An image may end on a row of a later line, which keeps its distance from this row.
+            CharCell::Image { ref mut end, .. }     => {
+                let relocated = moved.get(end).cloned().unwrap_or(Coords {
+                    x: end.x,
+                    y: new.y + end.y.saturating_sub(old.y),
+                });
+                *end = relocated;
+                Some(())
+            }
+            _                                       => Some(()),
+        };
+        if relocated.is_none() { cell.empty(); }
+        data[new.y as usize * width + new.x as usize] = cell;
+    }
+    data.chunks(width).enumerate().map(|(y, row)| {
+        CompactRow::new(row, y as u32, y + 1 < rows)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use datatypes::Coords;
+    use terminal::{CharCell, Styles};
+
+    #[test]
+    fn max_rows() {
+        assert_eq!(Scrollback::Rows(100).max_rows(10), Some(100));
+        assert_eq!(Scrollback::Rows(5).max_rows(10), Some(10));
+        assert_eq!(Scrollback::Unlimited { max_bytes: 0 }.max_rows(10), None);
+    }
+
+    #[test]
+    fn compact_row() {
+        let bold = Styles { bold: true, ..Styles::default() };
+        let cells = vec![
+            CharCell::Char('A', Styles::default()),
+            CharCell::Char('B', Styles::default()),
+            CharCell::Char('C', bold),
+            CharCell::Grapheme(String::from("E\u{301}"), bold),
+            CharCell::Char('\u{4e00}', bold),
+            CharCell::Extension(Coords { x: 4, y: 7 }, bold),
+            CharCell::Empty(Styles::default()),
+            CharCell::Empty(Styles::default()),
+        ];
+        let row = CompactRow::new(&cells, 7, true);
+        assert_eq!(row.runs.len(), 5);
+        assert!(row.is_wrapped());
+        let cells = row.cells(2, 10);
+        assert_eq!(cells.len(), 10);
+        let text: Vec<_> = cells.iter().map(CharCell::repr).collect();
+        assert_eq!(text, ["A", "B", "C", "E\u{301}", "\u{4e00}", "EXT", "", "", "", ""]);
+        assert_eq!(*cells[2].style(), bold);
+        match cells[5] {
+            CharCell::Extension(source, _)  => assert_eq!(source, Coords { x: 4, y: 2 }),
+            _                               => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn evict() {
+        let row = CompactRow::new(&[CharCell::Char('A', Styles::default())], 0, false);
+        let mut history = History::default();
+        for _ in 0..10 { history.push_back(row.clone()); }
+        assert_eq!(history.bytes(), row.bytes() * 10);
+        assert_eq!(history.evict(Scrollback::Rows(12), 5), 3);
+        assert_eq!(history.len(), 7);
+        let max_bytes = row.bytes() * 4;
+        assert_eq!(history.evict(Scrollback::Unlimited { max_bytes: max_bytes }, 5), 3);
+        assert_eq!(history.bytes(), max_bytes);
+        history.pop_back();
+        assert_eq!(history.len(), 3);
+    }
+
+    #[test]
+    fn evict_by_bytes() {
+        let short = CompactRow::new(&[CharCell::Char('A', Styles::default())], 0, false);
+        let cells: Vec<_> = "History".chars().map(|c| CharCell::Char(c, Styles::default()))
+                                     .collect();
+        let long = CompactRow::new(&cells, 0, false);
+        let mut history = History::default();
+        history.push_back(long.clone());
+        history.push_back(short.clone());
+        history.push_back(long.clone());
+        let max_bytes = long.bytes() + short.bytes();
+        assert_eq!(history.evict(Scrollback::Unlimited { max_bytes: max_bytes }, 100), 1);
+        assert_eq!(history.bytes(), max_bytes);
+        assert_eq!(history.evict(Scrollback::Unlimited { max_bytes: max_bytes - 1 }, 100), 1);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.get(0).unwrap().len(), 7);
+    }
+
+    #[test]
+    fn reflow() {
+        let row = |text: &str, wrapped| {
+            let cells: Vec<_> = text.chars().map(|c| CharCell::Char(c, Styles::default()))
+                                    .collect();
+            CompactRow::new(&cells, 0, wrapped)
+        };
+        let mut history = History::default();
+        history.push_back(row("ABCD", true));
+        history.push_back(row("EFGH", true));
+        history.push_back(row("IJ", false));
+        history.push_back(row("xy", false));
+        history.reflow(4, 5);
+        assert_eq!(history.len(), 3);
+        let text: Vec<_> = history.get(1).unwrap().cells(1, 5).iter().map(CharCell::repr)
+                                  .collect();
+        assert_eq!(text, ["F", "G", "H", "I", "J"]);
+        assert!(history.get(0).unwrap().is_wrapped() && !history.get(1).unwrap().is_wrapped());
+        assert_eq!(history.get(2).unwrap().len(), 2);
+        history.reflow(5, 10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0).unwrap().len(), 10);
+        assert!(!history.get(0).unwrap().is_wrapped());
+        let bytes = history.get(0).unwrap().bytes() + history.get(1).unwrap().bytes();
+        assert_eq!(history.bytes(), bytes);
+    }
+
+}
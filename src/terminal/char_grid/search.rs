@@ -0,0 +1,236 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp::{self, Ordering};
+
+use regex::{self, Regex};
+
+use datatypes::Coords;
+use terminal::CharCell;
+
+/// What to search the grid for.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SearchPattern {
+    Literal(String),
+    Regex(String),
+}
+
+/// A match of a search, from the first cell of the match to its last cell.
+///
+/// Rows are counted from the oldest row of scrollback, so that row `y` of the grid is row
+/// `scrollback_len() + y` of a match.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SearchMatch {
+    pub start: Coords,
+    pub end: Coords,
+}
+
+impl SearchMatch {
+    /// Whether this match covers a cell, counting rows the same way as the match does.
+    pub fn contains(&self, coords: Coords) -> bool {
+        position(self.start, coords) != Ordering::Greater
+            && position(coords, self.end) != Ordering::Greater
+    }
+}
+
+pub struct Search {
+    regex: Regex,
+    pub matches: Vec<SearchMatch>,
+    pub current: Option<SearchMatch>,
+    // Rows before this one belong to lines in the history which have been searched already.
+    pub scanned: u32,
+    // The count of rows dropped from the grid when the search was last updated.
+    pub dropped: usize,
+}
+
+impl Search {
+    pub fn new(pattern: &SearchPattern, dropped: usize) -> Result<Search, regex::Error> {
+        let regex = try!(match *pattern {
+            SearchPattern::Literal(ref s)   => Regex::new(&regex::quote(s)),
+            SearchPattern::Regex(ref s)     => Regex::new(s),
+        });
+        Ok(Search {
+            regex: regex,
+            matches: Vec::new(),
+            current: None,
+            scanned: 0,
+            dropped: dropped,
+        })
+    }
+
+    /// Forget every match, so that the whole grid is searched again.
+    pub fn reset(&mut self) {
+        self.matches.clear();
+        self.current = None;
+        self.scanned = 0;
+    }
+
+    /// Forget the matches on rows which are searched again from `scanned` on, and move the
+    /// other matches up by the number of rows dropped from the top of the grid.
+    pub fn prepare(&mut self, scanned: u32, dropped: usize) {
+        let n = dropped.wrapping_sub(self.dropped) as u32;
+        let scanned = cmp::min(self.scanned.saturating_sub(n), scanned);
+        self.dropped = dropped;
+        self.matches = self.matches.drain(..).filter(|m| {
+            m.start.y >= n && m.start.y - n < scanned
+        }).map(|m| move_up(m, n)).collect();
+        self.current = match self.current {
+            Some(m) if m.start.y >= n   => Some(move_up(m, n)),
+            _                           => None,
+        };
+        self.scanned = scanned;
+    }
+
+    /// Find the matches in a logical line, given as the rows of cells it spans beginning at row
+    /// `y`. Extension cells belong to the wide character before them.
+    pub fn find(&mut self, y: u32, rows: &[Vec<CharCell>]) {
+        let mut text = String::new();
+        let mut cells: Vec<(usize, Coords, Coords)> = Vec::new();
+        for (dy, row) in rows.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                let coords = Coords { x: x as u32, y: y + dy as u32 };
+                if let CharCell::Extension(source, _) = *cell {
+                    if let Some(last) = cells.last_mut() {
+                        if last.1.y == coords.y && last.1.x == source.x { last.2 = coords; }
+                    }
+                    continue;
+                }
+                cells.push((text.len(), coords, coords));
+                match *cell {
+                    CharCell::Empty(_)              => text.push(' '),
+                    CharCell::Char(c, _)            => text.push(c),
+                    CharCell::Grapheme(ref s, _)    => text.push_str(s),
+                    CharCell::Image { .. }          => text.push('\u{fffc}'),
+                    CharCell::Extension(..)         => unreachable!(),
+                }
+            }
+        }
+        let cell_at = |byte: usize| match cells.binary_search_by(|cell| cell.0.cmp(&byte)) {
+            Ok(n)   => n,
+            Err(n)  => n - 1,
+        };
+        for (start, end) in self.regex.find_iter(text.trim_right()) {
+            if start == end { continue; }
+            self.matches.push(SearchMatch {
+                start: cells[cell_at(start)].1,
+                end: cells[cell_at(end - 1)].2,
+            });
+        }
+    }
+
+    /// The match covering a cell, if there is one.
+    pub fn match_at(&self, coords: Coords) -> Option<SearchMatch> {
+        let n = match self.matches.binary_search_by(|m| position(m.start, coords)) {
+            Ok(n)   => n,
+            Err(0)  => return None,
+            Err(n)  => n - 1,
+        };
+        let m = self.matches[n];
+        if m.contains(coords) { Some(m) } else { None }
+    }
+
+    /// Make the match after (`forward`) or before the current match current, wrapping around
+    /// at either end. The last match is the first to become current going backward.
+    pub fn step(&mut self, forward: bool) -> Option<SearchMatch> {
+        let next = match (self.current, forward) {
+            (Some(current), true)   => self.matches.iter().find(|m| {
+                position(m.start, current.start) == Ordering::Greater
+            }).or(self.matches.first()),
+            (Some(current), false)  => self.matches.iter().rev().find(|m| {
+                position(m.start, current.start) == Ordering::Less
+            }).or(self.matches.last()),
+            (None, true)            => self.matches.first(),
+            (None, false)           => self.matches.last(),
+        }.cloned();
+        self.current = next;
+        next
+    }
+}
+
+fn move_up(m: SearchMatch, n: u32) -> SearchMatch {
+    SearchMatch {
+        start: Coords { x: m.start.x, y: m.start.y - n },
+        end: Coords { x: m.end.x, y: m.end.y - n },
+    }
+}
+
+// Order cells from the top left of the grid to its bottom right.
+fn position(a: Coords, b: Coords) -> Ordering {
+    (a.y, a.x).cmp(&(b.y, b.x))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use datatypes::Coords;
+    use terminal::{CharCell, Styles};
+
+    fn row(s: &str, width: usize) -> Vec<CharCell> {
+        let mut cells: Vec<_> = s.chars().map(|c| CharCell::Char(c, Styles::default())).collect();
+        cells.resize(width, CharCell::default());
+        cells
+    }
+
+    #[test]
+    fn find_across_rows() {
+        let mut search = Search::new(&SearchPattern::Literal(String::from("cdef")), 0).unwrap();
+        search.find(3, &[row("abcd", 4), row("efg", 4)]);
+        assert_eq!(search.matches, [SearchMatch {
+            start: Coords { x: 2, y: 3 },
+            end: Coords { x: 1, y: 4 },
+        }]);
+        assert!(search.match_at(Coords { x: 3, y: 3 }).is_some());
+        assert!(search.match_at(Coords { x: 2, y: 4 }).is_none());
+    }
+
+    #[test]
+    fn find_wide_chars() {
+        let mut cells = row("a\u{4e00}", 5);
+        cells.insert(2, CharCell::Extension(Coords { x: 1, y: 0 }, Styles::default()));
+        cells.insert(3, CharCell::Grapheme(String::from("e\u{301}"), Styles::default()));
+        let pattern = SearchPattern::Regex(String::from("\u{4e00}e\u{301}"));
+        let mut search = Search::new(&pattern, 0).unwrap();
+        search.find(0, &[cells]);
+        assert_eq!(search.matches, [SearchMatch {
+            start: Coords { x: 1, y: 0 },
+            end: Coords { x: 3, y: 0 },
+        }]);
+    }
+
+    #[test]
+    fn step() {
+        let mut search = Search::new(&SearchPattern::Regex(String::from("a+")), 0).unwrap();
+        search.find(0, &[row("a b aa", 6)]);
+        assert_eq!(search.matches.len(), 2);
+        assert_eq!(search.step(false).unwrap().start, Coords { x: 4, y: 0 });
+        assert_eq!(search.step(false).unwrap().start, Coords { x: 0, y: 0 });
+        assert_eq!(search.step(false).unwrap().start, Coords { x: 4, y: 0 });
+        assert_eq!(search.step(true).unwrap().start, Coords { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn prepare() {
+        let mut search = Search::new(&SearchPattern::Regex(String::from("a")), 0).unwrap();
+        for y in 0..4 { search.find(y, &[row("a", 1)]); }
+        search.scanned = 3;
+        search.prepare(3, 1);
+        assert_eq!(search.matches.len(), 2);
+        assert_eq!(search.matches[0].start, Coords { x: 0, y: 0 });
+        assert_eq!(search.scanned, 2);
+    }
+
+}
@@ -0,0 +1,202 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
+
+use datatypes::Coords;
+use terminal::CharCell;
+
+/// How a selection grows from the cell where it was started.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SelectionMode {
+    /// Every cell from one end of the selection to the other.
+    Char,
+    /// Like `Char`, but both ends are extended to the edges of the words they are in.
+    Word,
+    /// Every row from one end of the selection to the other.
+    Line,
+    /// A rectangle with the two ends of the selection at its corners.
+    Block,
+}
+
+/// A selection of cells. Rows are counted from the oldest row of scrollback, as they are for
+/// search matches, so that the selection stays on the same cells as the grid scrolls.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Selection {
+    pub mode: SelectionMode,
+    pub anchor: Coords,
+    pub head: Coords,
+    start: Coords,
+    end: Coords,
+}
+
+impl Selection {
+    pub fn new(mode: SelectionMode, anchor: Coords) -> Selection {
+        Selection {
+            mode: mode,
+            anchor: anchor,
+            head: anchor,
+            start: anchor,
+            end: anchor,
+        }
+    }
+
+    /// The first and last cells of the selection, or the top left and bottom right corners of
+    /// a block selection.
+    pub fn bounds(&self) -> (Coords, Coords) {
+        (self.start, self.end)
+    }
+
+    pub fn contains(&self, coords: Coords) -> bool {
+        match self.mode {
+            SelectionMode::Block    => {
+                self.start.x <= coords.x && coords.x <= self.end.x
+                    && self.start.y <= coords.y && coords.y <= self.end.y
+            }
+            _                       => {
+                (self.start.y, self.start.x) <= (coords.y, coords.x)
+                    && (coords.y, coords.x) <= (self.end.y, self.end.x)
+            }
+        }
+    }
+
+    /// Recompute the bounds of the selection from its anchor and head. `row` gives the cells of
+    /// a row; ends of the selection are moved off of the extension cells of wide characters.
+    pub fn update<F>(&mut self, row: F, separators: &str) where F: Fn(u32) -> Vec<CharCell> {
+        let (mut start, mut end) = if (self.anchor.y, self.anchor.x) <= (self.head.y, self.head.x) {
+            (self.anchor, self.head)
+        } else { (self.head, self.anchor) };
+        if self.mode == SelectionMode::Block {
+            self.start = Coords { x: cmp::min(start.x, end.x), y: start.y };
+            self.end = Coords { x: cmp::max(start.x, end.x), y: end.y };
+            return;
+        }
+        let first = row(start.y);
+        let last = if end.y == start.y { first.clone() } else { row(end.y) };
+        let is_word = |cell: &CharCell| match *cell {
+            CharCell::Char(c, _)            => !separators.contains(c),
+            CharCell::Grapheme(ref s, _)    => !s.chars().next().map_or(false, |c| {
+                separators.contains(c)
+            }),
+            CharCell::Extension(..)         => true,
+            _                               => false,
+        };
+        match self.mode {
+            SelectionMode::Word => {
+                while start.x > 0 && is_word(&first[start.x as usize])
+                        && is_word(&first[start.x as usize - 1]) {
+                    start.x -= 1;
+                }
+                while end.x as usize + 1 < last.len() && is_word(&last[end.x as usize])
+                        && is_word(&last[end.x as usize + 1]) {
+                    end.x += 1;
+                }
+            }
+            SelectionMode::Line => {
+                start.x = 0;
+                end.x = last.len().saturating_sub(1) as u32;
+            }
+            _                   => (),
+        }
+        if let Some(&CharCell::Extension(source, _)) = first.get(start.x as usize) {
+            start.x = cmp::min(source.x, start.x);
+        }
+        while last.get(end.x as usize + 1).map_or(false, CharCell::is_char_extension) {
+            end.x += 1;
+        }
+        self.start = start;
+        self.end = end;
+    }
+
+    /// Move the selection up as `n` rows are dropped from the top of the scrollback. Returns
+    /// false if every selected row was dropped.
+    pub fn move_up(&mut self, n: u32) -> bool {
+        if self.end.y < n { return false; }
+        for coords in &mut [&mut self.anchor, &mut self.head, &mut self.start] {
+            if coords.y < n {
+                **coords = Coords { x: 0, y: 0 };
+            } else {
+                coords.y -= n;
+            }
+        }
+        self.end.y -= n;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use cfg;
+    use datatypes::Coords;
+    use terminal::{CharCell, Styles};
+
+    fn row(y: u32) -> Vec<CharCell> {
+        let mut cells: Vec<_> = match y {
+            0   => "foo bar(baz)",
+            _   => "a \u{4e00}",
+        }.chars().map(|c| CharCell::Char(c, Styles::default())).collect();
+        if y != 0 { cells.push(CharCell::Extension(Coords { x: 2, y: y }, Styles::default())); }
+        cells.resize(12, CharCell::default());
+        cells
+    }
+
+    fn select(mode: SelectionMode, anchor: Coords, head: Coords) -> Selection {
+        let mut selection = Selection::new(mode, anchor);
+        selection.head = head;
+        selection.update(row, cfg::WORD_SEPARATORS);
+        selection
+    }
+
+    #[test]
+    fn char_mode() {
+        let selection = select(SelectionMode::Char, Coords { x: 3, y: 1 }, Coords { x: 2, y: 0 });
+        assert_eq!(selection.bounds(), (Coords { x: 2, y: 0 }, Coords { x: 3, y: 1 }));
+        assert!(selection.contains(Coords { x: 11, y: 0 }));
+        assert!(!selection.contains(Coords { x: 4, y: 1 }));
+        let selection = select(SelectionMode::Char, Coords { x: 2, y: 1 }, Coords { x: 2, y: 1 });
+        assert_eq!(selection.bounds(), (Coords { x: 2, y: 1 }, Coords { x: 3, y: 1 }));
+    }
+
+    #[test]
+    fn word_mode() {
+        let selection = select(SelectionMode::Word, Coords { x: 5, y: 0 }, Coords { x: 9, y: 0 });
+        assert_eq!(selection.bounds(), (Coords { x: 4, y: 0 }, Coords { x: 10, y: 0 }));
+        let selection = select(SelectionMode::Word, Coords { x: 3, y: 1 }, Coords { x: 3, y: 1 });
+        assert_eq!(selection.bounds(), (Coords { x: 2, y: 1 }, Coords { x: 3, y: 1 }));
+    }
+
+    #[test]
+    fn line_and_block_modes() {
+        let selection = select(SelectionMode::Line, Coords { x: 5, y: 0 }, Coords { x: 1, y: 1 });
+        assert_eq!(selection.bounds(), (Coords { x: 0, y: 0 }, Coords { x: 11, y: 1 }));
+        let selection = select(SelectionMode::Block, Coords { x: 5, y: 0 }, Coords { x: 1, y: 1 });
+        assert_eq!(selection.bounds(), (Coords { x: 1, y: 0 }, Coords { x: 5, y: 1 }));
+        assert!(!selection.contains(Coords { x: 6, y: 0 }));
+        assert!(selection.contains(Coords { x: 1, y: 1 }));
+    }
+
+    #[test]
+    fn move_up() {
+        let (anchor, head) = (Coords { x: 3, y: 0 }, Coords { x: 1, y: 1 });
+        let mut selection = select(SelectionMode::Char, anchor, head);
+        assert!(selection.move_up(1));
+        assert_eq!(selection.bounds(), (Coords { x: 0, y: 0 }, Coords { x: 1, y: 0 }));
+        assert!(!selection.move_up(1));
+    }
+
+}
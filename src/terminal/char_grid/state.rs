@@ -0,0 +1,351 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! The state of a grid as plain data, which can be serialized with serde and restored later.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::usize;
+
+use image::{self, ImageError, ImageFormat};
+
+use datatypes::{Color, Coords, MediaPosition};
+use datatypes::args::Argument;
+
+use super::{CharCell, CharGrid, Cursor, Grid, Scrollback, Styles, Tooltip};
+use super::damage::DamageTracker;
+use super::scrollback::{CompactRow, History};
+
+/// The reason a saved state could not be saved or restored.
+#[derive(Debug)]
+pub enum StateError {
+    /// The state was saved in a version of the format which this build does not read.
+    Version(u32),
+    /// An image could not be encoded or decoded as PNG.
+    Image(ImageError),
+    /// A part of the state is inconsistent with the rest, such as a row of the wrong width.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::Version(n)      => write!(f, "unsupported state version {}", n),
+            StateError::Image(ref err)  => write!(f, "invalid image in state: {}", err),
+            StateError::Invalid(what)   => write!(f, "invalid state: {}", what),
+        }
+    }
+}
+
+impl Error for StateError {
+    fn description(&self) -> &str {
+        match *self {
+            StateError::Version(_)      => "unsupported state version",
+            StateError::Image(_)        => "invalid image in state",
+            StateError::Invalid(what)   => what,
+        }
+    }
+}
+
+impl From<ImageError> for StateError {
+    fn from(err: ImageError) -> StateError {
+        StateError::Image(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GridState {
+    pub width: u32,
+    pub height: u32,
+    pub scrolls_x: bool,
+    /// The number of columns the grid can grow to by scrolling horizontally.
+    pub max_width: u32,
+    pub scrollback: Option<Scrollback>,
+    pub visible_width: u32,
+    pub visible_height: u32,
+    pub scroll_x: u32,
+    pub view_offset: u32,
+    /// Rows of history stored outside of the grid, from the oldest. Coordinates held by their
+    /// cells count rows the same way.
+    pub history: Vec<RowState>,
+    pub rows: Vec<RowState>,
+    pub cursor: CursorState,
+    pub tooltips: Vec<((u32, u32), Tooltip)>,
+    /// The targets of the hyperlinks which cells of the grid are part of.
+    pub links: Vec<((u32, u32), String)>,
+    pub hyperlink: Option<String>,
+    pub word_separators: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RowState {
+    pub cells: Vec<CellState>,
+    pub wrapped: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CellState {
+    Empty(StylesState),
+    Char(char, StylesState),
+    Grapheme(String, StylesState),
+    Image {
+        png: Vec<u8>,
+        /// The position of the image within its cells, in the notty argument encoding.
+        pos: String,
+        end: (u32, u32),
+        style: StylesState,
+    },
+    Extension((u32, u32), StylesState),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CursorState {
+    pub x: u32,
+    pub y: u32,
+    pub style: StylesState,
+    pub text_style: StylesState,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StylesState {
+    pub fg_color: (u8, u8, u8),
+    pub bg_color: (u8, u8, u8),
+    pub opacity: u8,
+    pub underline: bool,
+    pub double_underline: bool,
+    pub bold: bool,
+    pub italic: bool,
+    pub strikethrough: bool,
+    pub inverted: bool,
+    pub blink: bool,
+    pub protected: bool,
+}
+
+impl CharGrid {
+    /// Save the contents of this grid and how it is viewed. Searches and selections are not
+    /// saved.
+    pub fn state(&self) -> Result<GridState, StateError> {
+        let width = self.grid.width;
+        let history: Result<Vec<RowState>, StateError> = (0..self.history.len()).map(|n| {
+            let row = self.history.get(n).unwrap();
+            row_state(&row.cells(n as u32, width), row.is_wrapped())
+        }).collect();
+        let rows: Result<Vec<RowState>, StateError> = (0..self.grid.height).map(|y| {
+            let cells: Vec<CharCell> = (0..width).map(|x| {
+                self.grid[Coords { x: x as u32, y: y as u32 }].clone()
+            }).collect();
+            row_state(&cells, self.grid.is_wrapped(y))
+        }).collect();
+        let mut tooltips: Vec<_> = self.tooltips.iter().map(|(coords, tooltip)| {
+            ((coords.x, coords.y), tooltip.clone())
+        }).collect();
+        tooltips.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut links: Vec<_> = self.links.iter().map(|(coords, uri)| {
+            ((coords.x, coords.y), uri.clone())
+        }).collect();
+        links.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(GridState {
+            width: width as u32,
+            height: self.grid.height as u32,
+            scrolls_x: self.grid.scrolls_x,
+            max_width: self.grid.max_width() as u32,
+            scrollback: self.scrollback,
+            visible_width: self.visible_width,
+            visible_height: self.visible_height,
+            scroll_x: self.scroll_x,
+            view_offset: self.view_offset,
+            history: try!(history),
+            rows: try!(rows),
+            cursor: CursorState {
+                x: self.cursor.coords.x,
+                y: self.cursor.coords.y,
+                style: styles_state(&self.cursor.style),
+                text_style: styles_state(&self.cursor.text_style),
+            },
+            tooltips: tooltips,
+            links: links,
+            hyperlink: self.hyperlink.clone(),
+            word_separators: self.word_separators.clone(),
+        })
+    }
+
+    /// Rebuild a grid from a saved state, checking that the state is consistent so that the
+    /// grid cannot be left pointing outside of itself.
+    pub fn from_state(state: &GridState) -> Result<CharGrid, StateError> {
+        let width = state.width as usize;
+        let height = state.height as usize;
+        if width == 0 || height == 0 || state.visible_width == 0 || state.visible_height == 0 {
+            return Err(StateError::Invalid("empty grid"));
+        }
+        if state.rows.len() != height {
+            return Err(StateError::Invalid("wrong number of rows"));
+        }
+        if state.cursor.x >= state.width || state.cursor.y >= state.height {
+            return Err(StateError::Invalid("cursor outside of grid"));
+        }
+        if state.max_width < state.width {
+            return Err(StateError::Invalid("grid wider than its maximum width"));
+        }
+        let max_x = if state.scrolls_x { state.max_width as usize } else { 0 };
+        let max_y = state.scrollback.map_or(0, |s| {
+            s.max_rows(state.visible_height as usize).unwrap_or(usize::MAX)
+        });
+        let mut grid = Grid::with_x_y_caps(width, height, max_x, max_y);
+        for (y, row) in state.rows.iter().enumerate() {
+            let cells = try!(row_cells(row, y, width, height));
+            for (x, cell) in cells.into_iter().enumerate() {
+                grid[Coords { x: x as u32, y: y as u32 }] = cell;
+            }
+            grid.set_wrapped(y, row.wrapped);
+        }
+        let mut history = History::default();
+        let total = state.history.len() + height;
+        for (y, row) in state.history.iter().enumerate() {
+            let cells = try!(row_cells(row, y, width, total));
+            history.push_back(CompactRow::new(&cells, y as u32, row.wrapped));
+        }
+        let mut tooltips = HashMap::new();
+        for &((x, y), ref tooltip) in &state.tooltips {
+            if x >= state.width || y >= state.height {
+                return Err(StateError::Invalid("tooltip outside of grid"));
+            }
+            tooltips.insert(Coords { x: x, y: y }, tooltip.clone());
+        }
+        let mut links = HashMap::new();
+        for &((x, y), ref uri) in &state.links {
+            if x >= state.width || y >= state.height {
+                return Err(StateError::Invalid("hyperlink outside of grid"));
+            }
+            links.insert(Coords { x: x, y: y }, uri.clone());
+        }
+        let mut char_grid = CharGrid {
+            grid: grid,
+            cursor: Cursor {
+                coords: Coords { x: state.cursor.x, y: state.cursor.y },
+                style: styles(&state.cursor.style),
+                text_style: styles(&state.cursor.text_style),
+            },
+            tooltips: tooltips,
+            links: links,
+            hyperlink: state.hyperlink.clone(),
+            scrollback: state.scrollback,
+            history: history,
+            rows_dropped: 0,
+            search: None,
+            search_stale: false,
+            selection: None,
+            word_separators: state.word_separators.clone(),
+            damage: DamageTracker::new(state.visible_height),
+            wrapped_from: None,
+            visible_height: state.visible_height,
+            visible_width: state.visible_width,
+            scroll_x: 0,
+            view_offset: 0,
+            grid_width: state.width,
+            grid_height: state.height,
+        };
+        char_grid.set_scroll_x(state.scroll_x);
+        char_grid.set_view_offset(state.view_offset);
+        Ok(char_grid)
+    }
+}
+
+fn row_state(cells: &[CharCell], wrapped: bool) -> Result<RowState, StateError> {
+    let cells: Result<Vec<CellState>, StateError> = cells.iter().map(|cell| Ok(match *cell {
+        CharCell::Empty(style)              => CellState::Empty(styles_state(&style)),
+        CharCell::Char(c, style)            => CellState::Char(c, styles_state(&style)),
+        CharCell::Grapheme(ref s, style)    => CellState::Grapheme(s.clone(), styles_state(&style)),
+        CharCell::Image { ref data, ref pos, end, style }   => {
+            let mut png = Vec::new();
+            try!(data.save(&mut png, ImageFormat::PNG));
+            CellState::Image {
+                png: png,
+                pos: pos.encode(),
+                end: (end.x, end.y),
+                style: styles_state(&style),
+            }
+        }
+        CharCell::Extension(source, style)  => {
+            CellState::Extension((source.x, source.y), styles_state(&style))
+        }
+    })).collect();
+    Ok(RowState { cells: try!(cells), wrapped: wrapped })
+}
+
+// The cells of row y of a grid or its history, which must only refer to cells of the same grid
+// or history, up to `height` rows.
+fn row_cells(row: &RowState, y: usize, width: usize, height: usize)
+        -> Result<Vec<CharCell>, StateError> {
+    if row.cells.len() != width { return Err(StateError::Invalid("row of the wrong width")); }
+    row.cells.iter().enumerate().map(|(x, cell)| Ok(match *cell {
+        CellState::Empty(ref style)             => CharCell::Empty(styles(style)),
+        CellState::Char(c, ref style)           => CharCell::Char(c, styles(style)),
+        CellState::Grapheme(ref s, ref style)   => CharCell::Grapheme(s.clone(), styles(style)),
+        CellState::Image { ref png, ref pos, end: (end_x, end_y), ref style } => {
+            if end_x < x as u32 || end_x as usize >= width || end_y < y as u32
+                    || end_y as usize >= height {
+                return Err(StateError::Invalid("image extends outside of grid"));
+            }
+            let pos = match MediaPosition::decode(Some(&pos[..]), None) {
+                Some(pos)   => pos,
+                None        => return Err(StateError::Invalid("unknown image position")),
+            };
+            let data = try!(image::load_from_memory(png));
+            CharCell::image(data, pos, Coords { x: end_x, y: end_y }, styles(style))
+        }
+        CellState::Extension((src_x, src_y), ref style) => {
+            if src_x as usize >= width || src_y > y as u32 {
+                return Err(StateError::Invalid("wide character outside of grid"));
+            }
+            CharCell::Extension(Coords { x: src_x, y: src_y }, styles(style))
+        }
+    })).collect()
+}
+
+fn styles_state(styles: &Styles) -> StylesState {
+    let Color(fg_r, fg_g, fg_b) = styles.fg_color;
+    let Color(bg_r, bg_g, bg_b) = styles.bg_color;
+    StylesState {
+        fg_color: (fg_r, fg_g, fg_b),
+        bg_color: (bg_r, bg_g, bg_b),
+        opacity: styles.opacity,
+        underline: styles.underline,
+        double_underline: styles.double_underline,
+        bold: styles.bold,
+        italic: styles.italic,
+        strikethrough: styles.strikethrough,
+        inverted: styles.inverted,
+        blink: styles.blink,
+        protected: styles.protected,
+    }
+}
+
+fn styles(state: &StylesState) -> Styles {
+    let (fg_r, fg_g, fg_b) = state.fg_color;
+    let (bg_r, bg_g, bg_b) = state.bg_color;
+    Styles {
+        fg_color: Color(fg_r, fg_g, fg_b),
+        bg_color: Color(bg_r, bg_g, bg_b),
+        opacity: state.opacity,
+        underline: state.underline,
+        double_underline: state.double_underline,
+        bold: state.bold,
+        italic: state.italic,
+        strikethrough: state.strikethrough,
+        inverted: state.inverted,
+        blink: state.blink,
+        protected: state.protected,
+    }
+}
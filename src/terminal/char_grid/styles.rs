@@ -29,6 +29,7 @@ pub struct Styles {
     pub strikethrough: bool,
     pub inverted: bool,
     pub blink: bool,
+    pub protected: bool,
 }
 
 impl Styles {
@@ -36,8 +37,7 @@ impl Styles {
         match style {
             Underline(0)            => { self.underline = false; self.double_underline = false; }
             Underline(1)            => { self.underline = true;  self.double_underline = false; }
-            Underline(2)            => { self.underline = false; self.double_underline = true;  }
-            Underline(_)            => unreachable!(),
+            Underline(_)            => { self.underline = false; self.double_underline = true;  }
             Bold(flag)              => self.bold = flag,
             Italic(flag)            => self.italic = flag,
             Strikethrough(flag)     => self.strikethrough = flag,
@@ -67,6 +67,7 @@ impl Default for Styles {
             strikethrough:      false,
             inverted:           false,
             blink:              false,
+            protected:          false,
         }
     }
 }
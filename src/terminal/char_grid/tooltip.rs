@@ -19,12 +19,13 @@ use datatypes::Key;
 
 use self::Tooltip::*;
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Tooltip {
     Basic(String),
     Menu {
         options: Vec<String>,
         position: Option<usize>,
-    }
+    },
 }
 
 impl Tooltip {
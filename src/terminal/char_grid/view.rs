@@ -0,0 +1,47 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use datatypes::{CoordsIter, Region};
+use terminal::CharCell;
+
+use super::Grid;
+
+/// The cells in view, row by row.
+pub struct ViewCells<'a> {
+    grid: &'a Grid<CharCell>,
+    coords: CoordsIter,
+}
+
+impl<'a> ViewCells<'a> {
+    pub fn new(grid: &'a Grid<CharCell>, region: Region) -> ViewCells<'a> {
+        ViewCells {
+            grid: grid,
+            coords: CoordsIter::from_region(region),
+        }
+    }
+}
+
+impl<'a> Iterator for ViewCells<'a> {
+    type Item = &'a CharCell;
+
+    fn next(&mut self) -> Option<&'a CharCell> {
+        let grid = self.grid;
+        self.coords.next().map(|coords| &grid[coords])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.coords.size_hint()
+    }
+}
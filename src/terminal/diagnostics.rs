@@ -0,0 +1,283 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use cfg;
+
+/// A problem found in the output of the controlling process.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Problem {
+    /// A well-formed sequence for a feature which notty does not support.
+    Unsupported,
+    /// Output which could not be parsed, and was dropped.
+    Malformed,
+    /// A sequence which was interrupted by an unexpected byte. The sequence was abandoned and the
+    /// byte was parsed as the beginning of new output.
+    Recovered,
+}
+
+impl Problem {
+    pub fn severity(&self) -> Severity {
+        match *self {
+            Problem::Malformed      => Severity::Error,
+            Problem::Unsupported    => Severity::Warning,
+            Problem::Recovered      => Severity::Info,
+        }
+    }
+}
+
+// The least serious problems which are passed to the sink unless the verbosity is changed.
+const DEFAULT_VERBOSITY: Severity = Severity::Warning;
+
+/// How serious a problem is, from most to least.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+/// Receives the problems found in the output of the controlling process, after they have been
+/// filtered by verbosity and rate limited.
+pub trait DiagnosticSink {
+    fn report(&mut self, problem: Problem, detail: &str);
+
+    /// A number of problems were not reported because the rate limit had been reached.
+    fn suppressed(&mut self, _count: u64) { }
+}
+
+/// Appends each report as a line to a log file, creating the file and its directory if they do
+/// not exist.
+pub struct LogFile {
+    path: PathBuf,
+    // The log, opened when the first line is written; `None` within if it could not be opened.
+    file: Option<Option<File>>,
+}
+
+impl LogFile {
+    /// Log to a path, in which a leading `~` is the home directory.
+    pub fn new(path: &str) -> LogFile {
+        let path = match (path.starts_with("~/"), env::home_dir()) {
+            (true, Some(home))  => home.join(&path[2..]),
+            _                   => PathBuf::from(path),
+        };
+        LogFile { path: path, file: None }
+    }
+
+    fn write(&mut self, line: &str) {
+        if self.file.is_none() { self.file = Some(self.open()); }
+        if let Some(Some(ref mut file)) = self.file {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn open(&self) -> Option<File> {
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        OpenOptions::new().append(true).create(true).open(&self.path).ok()
+    }
+}
+
+impl Default for LogFile {
+    fn default() -> LogFile {
+        LogFile::new(cfg::LOGFILE)
+    }
+}
+
+impl DiagnosticSink for LogFile {
+    fn report(&mut self, problem: Problem, detail: &str) {
+        self.write(&format!("{:?}: {}", problem, detail));
+    }
+
+    fn suppressed(&mut self, count: u64) {
+        self.write(&format!("{} problems not reported", count));
+    }
+}
+
+/// Ignores every report.
+#[derive(Copy, Clone, Default)]
+pub struct NoDiagnostics;
+
+impl DiagnosticSink for NoDiagnostics {
+    fn report(&mut self, _: Problem, _: &str) { }
+}
+
+/// Counts the problems found in the output of the controlling process, and passes those at
+/// least as serious as its verbosity to a sink, at most `max` in each `period`.
+pub struct Diagnostics {
+    sink: Box<DiagnosticSink>,
+    verbosity: Option<Severity>,
+    limit: Option<(u32, Duration)>,
+    window: Option<Instant>,
+    sent: u32,
+    suppressed: u64,
+    total_suppressed: u64,
+    counts: HashMap<Problem, u64>,
+}
+
+impl Diagnostics {
+    pub fn new<S: DiagnosticSink + 'static>(sink: S) -> Diagnostics {
+        Diagnostics {
+            sink: Box::new(sink),
+            verbosity: Some(DEFAULT_VERBOSITY),
+            limit: Some((cfg::DIAGNOSTIC_LIMIT, Duration::from_secs(1))),
+            window: None,
+            sent: 0,
+            suppressed: 0,
+            total_suppressed: 0,
+            counts: HashMap::new(),
+        }
+    }
+
+    pub fn report(&mut self, problem: Problem, detail: &str) {
+        *self.counts.entry(problem).or_insert(0) += 1;
+        match self.verbosity {
+            Some(verbosity) if problem.severity() <= verbosity  => (),
+            _                                                   => return,
+        }
+        if let Some((max, period)) = self.limit {
+            let now = Instant::now();
+            match self.window {
+                Some(start) if now.duration_since(start) < period   => (),
+                _                                                   => {
+                    self.end_window();
+                    self.window = Some(now);
+                }
+            }
+            if self.sent >= max {
+                self.suppressed += 1;
+                self.total_suppressed += 1;
+                return;
+            }
+            self.sent += 1;
+        }
+        self.sink.report(problem, detail);
+    }
+
+    /// How many problems of this kind have been found, whether or not they were reported.
+    pub fn count(&self, problem: Problem) -> u64 {
+        self.counts.get(&problem).cloned().unwrap_or(0)
+    }
+
+    /// How many problems were not reported because of the rate limit.
+    pub fn suppressed(&self) -> u64 {
+        self.total_suppressed
+    }
+
+    /// Report only problems at least this serious; `None` reports nothing.
+    pub fn set_verbosity(&mut self, verbosity: Option<Severity>) {
+        self.verbosity = verbosity;
+    }
+
+    /// Report at most `max` problems in each `period`; `None` reports every problem.
+    pub fn set_rate_limit(&mut self, limit: Option<(u32, Duration)>) {
+        self.end_window();
+        self.limit = limit;
+    }
+
+    pub fn set_sink<S: DiagnosticSink + 'static>(&mut self, sink: S) {
+        self.end_window();
+        self.sink = Box::new(sink);
+    }
+
+    fn end_window(&mut self) {
+        if self.suppressed > 0 { self.sink.suppressed(self.suppressed); }
+        self.window = None;
+        self.sent = 0;
+        self.suppressed = 0;
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Diagnostics {
+        Diagnostics::new(LogFile::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use headless::Headless;
+    use super::*;
+
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl DiagnosticSink for Recorder {
+        fn report(&mut self, problem: Problem, detail: &str) {
+            self.0.borrow_mut().push(format!("{:?} {}", problem, detail));
+        }
+        fn suppressed(&mut self, count: u64) {
+            self.0.borrow_mut().push(format!("suppressed {}", count));
+        }
+    }
+
+    fn setup() -> (Diagnostics, Rc<RefCell<Vec<String>>>) {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        (Diagnostics::new(Recorder(reports.clone())), reports)
+    }
+
+    #[test]
+    fn verbosity() {
+        let (mut diagnostics, reports) = setup();
+        diagnostics.set_verbosity(Some(Severity::Warning));
+        diagnostics.report(Problem::Unsupported, "a");
+        diagnostics.report(Problem::Recovered, "b");
+        diagnostics.report(Problem::Malformed, "c");
+        diagnostics.set_verbosity(None);
+        diagnostics.report(Problem::Malformed, "d");
+        assert_eq!(*reports.borrow(), ["Unsupported a", "Malformed c"]);
+        assert_eq!(diagnostics.count(Problem::Malformed), 2);
+        assert_eq!(diagnostics.count(Problem::Recovered), 1);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let (mut diagnostics, reports) = setup();
+        diagnostics.set_rate_limit(Some((2, Duration::from_secs(3600))));
+        for detail in &["a", "b", "c", "d"] {
+            diagnostics.report(Problem::Malformed, detail);
+        }
+        assert_eq!(*reports.borrow(), ["Malformed a", "Malformed b"]);
+        diagnostics.set_rate_limit(None);
+        diagnostics.report(Problem::Malformed, "e");
+        assert_eq!(*reports.borrow(), ["Malformed a", "Malformed b", "suppressed 2",
+                                       "Malformed e"]);
+        assert_eq!(diagnostics.suppressed(), 2);
+        assert_eq!(diagnostics.count(Problem::Malformed), 5);
+    }
+
+    #[test]
+    fn terminal_reports() {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut headless = Headless::new(10, 2);
+        headless.terminal_mut().diagnostics_mut().set_sink(Recorder(reports.clone()));
+        headless.feed(b"\x1b[5y\xffA\x1b[1:B").unwrap();
+        assert_eq!(*reports.borrow(), ["Unsupported ^[[5y", "Malformed invalid UTF-8 byte 0xff"]);
+        let diagnostics = headless.terminal().diagnostics();
+        assert_eq!(diagnostics.count(Problem::Unsupported), 1);
+        assert_eq!(diagnostics.count(Problem::Malformed), 1);
+        assert_eq!(diagnostics.count(Problem::Recovered), 1);
+    }
+}
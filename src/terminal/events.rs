@@ -0,0 +1,61 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use datatypes::InputMode;
+
+/// Receives the side effects of the terminal which the embedding application has to carry out,
+/// such as ringing the bell or changing the title of its window. Every method does nothing by
+/// default, so implementers only need to handle the events they care about.
+pub trait TerminalEvents {
+    /// The controlling process rang the bell.
+    fn bell(&mut self) { }
+
+    /// The controlling process changed the title of the terminal.
+    fn title_changed(&mut self, _title: &str) { }
+
+    /// The controlling process asked for text to be copied to the clipboard.
+    fn set_clipboard(&mut self, _text: &str) { }
+
+    /// The controlling process reported its current working directory.
+    fn cwd_changed(&mut self, _cwd: &str) { }
+
+    /// The controlling process asked for the terminal to be resized to a number of columns and
+    /// rows. A dimension of 0 is to be left as it is.
+    fn resize_requested(&mut self, _cols: u32, _rows: u32) { }
+
+    /// The user activated a hyperlink in the grid.
+    fn hyperlink_activated(&mut self, _uri: &str) { }
+
+    /// The controlling process changed a mode which affects what it receives from the terminal.
+    fn mode_changed(&mut self, _mode: Mode) { }
+}
+
+/// A mode of the terminal which changes the input sent to the controlling process, reported to
+/// `TerminalEvents::mode_changed` with its new setting.
+#[derive(Copy, Clone)]
+pub enum Mode {
+    Input(InputMode),
+    KeypadApplication(bool),
+    FocusReporting(bool),
+    SynchronizedUpdate(bool),
+    KeyboardFlags(u32),
+    ModifyOtherKeys(u32),
+}
+
+/// Ignores every event.
+#[derive(Copy, Clone, Default)]
+pub struct NoEvents;
+
+impl TerminalEvents for NoEvents { }
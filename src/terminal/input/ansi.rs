@@ -22,7 +22,7 @@ use super::modifiers::Modifiers;
 
 macro_rules! term_key {
     ($term:expr, $app_mode:expr, $mods:expr) => (match $mods.triplet() {
-        (false, false, false) if $app_mode  => Some(Cow::Borrowed(concat!("\x1b0", $term))),
+        (false, false, false) if $app_mode  => Some(Cow::Borrowed(concat!("\x1bO", $term))),
         (false, false, false)               => Some(Cow::Borrowed(concat!("\x1b[", $term))),
         (true,  false, false)               => Some(Cow::Borrowed(concat!("\x1b[1;2", $term))),
         (false, false, true)                => Some(Cow::Borrowed(concat!("\x1b[1;3", $term))),
@@ -47,8 +47,11 @@ macro_rules! tilde_key {
     });
 }
 
-pub fn encode(key: &Key, app_mode: bool, mods: Modifiers) -> Option<Cow<'static, str>> {
-    match *key {
+pub fn encode(key: &Key, app_mode: bool, keypad_mode: bool, other_keys: u32, mods: Modifiers)
+        -> Option<Cow<'static, str>> {
+    if let Some(code) = modify_other_key(key, other_keys, mods) { return Some(code); }
+    let key = if mods.ctrl() { key.clone().ctrl_modify() } else { key.clone() };
+    match key {
         Char(c) if mods.alt()   => Some(Cow::Owned(format!("\x1b{}", c))),
         Char(c)                 => Some(Cow::Owned(c.to_string())),
         UpArrow                 => term_key!('A', app_mode, mods),
@@ -57,30 +60,228 @@ pub fn encode(key: &Key, app_mode: bool, mods: Modifiers) -> Option<Cow<'static,
         RightArrow              => term_key!('C', app_mode, mods),
         Enter                   => Some(Cow::Borrowed("\n")),
         Backspace               => Some(Cow::Borrowed("\x08")),
-        Meta | Menu             => None,
+        Menu                    => None,
         PageUp                  => tilde_key!('5', mods),
         PageDown                => tilde_key!('6', mods),
-        Home                    => term_key!('H', false, mods),
-        End                     => term_key!('F', false, mods),
+        Home                    => term_key!('H', app_mode, mods),
+        End                     => term_key!('F', app_mode, mods),
         Insert                  => tilde_key!('2', mods),
         Delete                  => tilde_key!('3', mods),
-        NumLock                 => unimplemented!(),
-        ScrollLock              => unimplemented!(),
-        Function(n)             => match n {
-            0   => term_key!('P', true, mods), 
-            1   => term_key!('Q', true, mods),
-            2   => term_key!('R', true, mods),
-            4   => term_key!('S', true, mods),
-            _   => unimplemented!(),
-        },
-        MenuSelection(_)        => unimplemented!(),
+        NumLock                 => None,
+        ScrollLock              => None,
+        Function(n)             => function_key(n, mods),
+        Keypad(c)               => keypad_key(c, keypad_mode, mods),
+        MenuSelection(_)        => None,
+        Cmd(s)                  => Some(s),
         ShiftLeft
             | ShiftRight
             | CtrlLeft
             | CtrlRight
             | AltLeft
             | AltGr
-            | CapsLock
-            | Cmd(_)            => unreachable!(),
+            | Meta
+            | Super
+            | Hyper
+            | CapsLock          => unreachable!(),
     }
 }
+
+/// Encode F1 through F24. As in xterm, F13 through F24 are sent as F1 through F12 with the shift
+/// modifier.
+fn function_key(n: u8, mods: Modifiers) -> Option<Cow<'static, str>> {
+    let (n, shifted) = match n {
+        1...12  => (n as u32, false),
+        13...24 => (n as u32 - 12, true),
+        _       => return None,
+    };
+    let (code, term) = match n {
+        1       => (1, 'P'),
+        2       => (1, 'Q'),
+        3       => (1, 'R'),
+        4       => (1, 'S'),
+        5       => (15, '~'),
+        6...10  => (n + 11, '~'),
+        _       => (n + 12, '~'),
+    };
+    let m = ((modifier_param(mods) - 1) | shifted as u32) + 1;
+    Some(Cow::Owned(match (m, term) {
+        (1, '~')    => format!("\x1b[{}~", code),
+        (1, _)      => format!("\x1bO{}", term),
+        (_, '~')    => format!("\x1b[{};{}~", code, m),
+        (_, _)      => format!("\x1b[1;{}{}", m, term),
+    }))
+}
+
+/// Encode a key on the numeric keypad. In numeric mode (DECKPNM) the key sends the character
+/// printed on it; in application mode (DECKPAM) it sends an SS3 sequence.
+fn keypad_key(c: char, app_mode: bool, mods: Modifiers) -> Option<Cow<'static, str>> {
+    let term = match c {
+        '0'...'9'   => (b'p' + (c as u8 - b'0')) as char,
+        '.'         => 'n',
+        ','         => 'l',
+        '+'         => 'k',
+        '-'         => 'm',
+        '*'         => 'j',
+        '/'         => 'o',
+        '='         => 'X',
+        '\n'        => 'M',
+        _           => return None,
+    };
+    match (app_mode, modifier_param(mods)) {
+        (false, _)  => Some(Cow::Owned(c.to_string())),
+        (true, 1)   => Some(Cow::Owned(format!("\x1bO{}", term))),
+        (true, m)   => Some(Cow::Owned(format!("\x1bO{}{}", m, term))),
+    }
+}
+
+// The xterm modifier parameter: 1 plus a bit for each of shift, alt and control.
+fn modifier_param(mods: Modifiers) -> u32 {
+    let (shift, ctrl, alt) = mods.triplet();
+    1 + shift as u32 + 2 * alt as u32 + 4 * ctrl as u32
+}
+
+/// Encode a modified key as `CSI 27 ; modifiers ; code ~` according to xterm's modifyOtherKeys
+/// level. Level 1 leaves keys with a well-known encoding, such as control characters, alone;
+/// level 2 encodes every key modified by control or alt.
+fn modify_other_key(key: &Key, level: u32, mods: Modifiers) -> Option<Cow<'static, str>> {
+    let (_, ctrl, alt) = mods.triplet();
+    if level == 0 || !(ctrl || alt) { return None; }
+    let (code, well_known) = match *key {
+        Char(c) if ctrl => (c as u32, match c {
+            '\x00'...'\x20' | '\x7f' | 'a'...'z'         => true,
+            '@' | '[' | '\\' | ']' | '^' | '_' | '?'     => true,
+            _                                           => false,
+        }),
+        Char(c)         => (c as u32, true),
+        Enter           => (13, true),
+        Backspace       => (127, true),
+        _               => return None,
+    };
+    if level == 1 && well_known { return None; }
+    Some(Cow::Owned(format!("\x1b[27;{};{}~", modifier_param(mods), code)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::Key;
+    use datatypes::Key::*;
+    use terminal::input::modifiers::Modifiers;
+
+    use super::encode;
+
+    fn mods(keys: &[Key]) -> Modifiers {
+        let mut mods = Modifiers::new();
+        for key in keys { mods.apply(key, true); }
+        mods
+    }
+
+    static OTHER_KEYS: &'static [(&'static [Key], Key, u32, &'static str)] = &[
+        (&[], Char('a'), 2, "a"),
+        (&[ShiftLeft], Char('A'), 2, "A"),
+        (&[CtrlLeft], Char('a'), 0, "\x01"),
+        (&[CtrlLeft], Char('a'), 1, "\x01"),
+        (&[CtrlLeft], Char('a'), 2, "\x1b[27;5;97~"),
+        (&[CtrlLeft], Char('1'), 0, "1"),
+        (&[CtrlLeft], Char('1'), 1, "\x1b[27;5;49~"),
+        (&[CtrlLeft], Char(','), 1, "\x1b[27;5;44~"),
+        (&[CtrlLeft, ShiftLeft], Char('A'), 1, "\x1b[27;6;65~"),
+        (&[CtrlLeft], Char('\x09'), 1, "\x09"),
+        (&[CtrlLeft], Char('\x09'), 2, "\x1b[27;5;9~"),
+        (&[AltLeft], Char('x'), 1, "\x1bx"),
+        (&[AltLeft], Char('x'), 2, "\x1b[27;3;120~"),
+        (&[CtrlLeft, AltLeft], Char('.'), 1, "\x1b[27;7;46~"),
+        (&[CtrlLeft], Enter, 2, "\x1b[27;5;13~"),
+        (&[CtrlLeft], UpArrow, 2, "\x1b[1;5A"),
+    ];
+
+    static KEYS: &'static [(&'static [Key], Key, bool, Option<&'static str>)] = &[
+        (&[], Char('a'), false, Some("a")),
+        (&[AltLeft], Char('a'), false, Some("\x1ba")),
+        (&[CtrlLeft], Char('a'), false, Some("\x01")),
+        (&[], UpArrow, false, Some("\x1b[A")),
+        (&[], UpArrow, true, Some("\x1bOA")),
+        (&[ShiftLeft], UpArrow, true, Some("\x1b[1;2A")),
+        (&[], DownArrow, false, Some("\x1b[B")),
+        (&[CtrlLeft, AltLeft], DownArrow, false, Some("\x1b[1;7B")),
+        (&[], LeftArrow, true, Some("\x1bOD")),
+        (&[], RightArrow, false, Some("\x1b[C")),
+        (&[], Enter, false, Some("\n")),
+        (&[], Backspace, false, Some("\x08")),
+        (&[], Menu, false, None),
+        (&[], PageUp, false, Some("\x1b[5~")),
+        (&[CtrlLeft], PageDown, false, Some("\x1b[6;5~")),
+        (&[], Home, false, Some("\x1b[H")),
+        (&[], Home, true, Some("\x1bOH")),
+        (&[ShiftLeft], End, false, Some("\x1b[1;2F")),
+        (&[], Insert, false, Some("\x1b[2~")),
+        (&[ShiftLeft], Delete, false, Some("\x1b[3;2~")),
+        (&[], NumLock, false, None),
+        (&[], ScrollLock, false, None),
+        (&[], MenuSelection(2), false, None),
+        (&[], Function(1), false, Some("\x1bOP")),
+        (&[], Function(2), false, Some("\x1bOQ")),
+        (&[], Function(3), false, Some("\x1bOR")),
+        (&[], Function(4), false, Some("\x1bOS")),
+        (&[], Function(5), false, Some("\x1b[15~")),
+        (&[], Function(6), false, Some("\x1b[17~")),
+        (&[], Function(7), false, Some("\x1b[18~")),
+        (&[], Function(8), false, Some("\x1b[19~")),
+        (&[], Function(9), false, Some("\x1b[20~")),
+        (&[], Function(10), false, Some("\x1b[21~")),
+        (&[], Function(11), false, Some("\x1b[23~")),
+        (&[], Function(12), false, Some("\x1b[24~")),
+        (&[], Function(13), false, Some("\x1b[1;2P")),
+        (&[], Function(16), false, Some("\x1b[1;2S")),
+        (&[], Function(17), false, Some("\x1b[15;2~")),
+        (&[], Function(24), false, Some("\x1b[24;2~")),
+        (&[CtrlLeft], Function(1), false, Some("\x1b[1;5P")),
+        (&[ShiftLeft, AltLeft], Function(3), false, Some("\x1b[1;4R")),
+        (&[CtrlLeft], Function(5), false, Some("\x1b[15;5~")),
+        (&[CtrlLeft], Function(13), false, Some("\x1b[1;6P")),
+        (&[], Function(0), false, None),
+        (&[], Function(25), false, None),
+    ];
+
+    #[test]
+    fn keys() {
+        for &(held, ref key, app_mode, code) in KEYS {
+            assert_eq!(encode(key, app_mode, false, 0, mods(held)).as_ref().map(|s| &s[..]),
+                       code, "{:?}", key);
+        }
+    }
+
+    static KEYPAD: &'static [(&'static [Key], char, bool, &'static str)] = &[
+        (&[], '0', false, "0"),
+        (&[], '7', false, "7"),
+        (&[], '.', false, "."),
+        (&[], '+', false, "+"),
+        (&[], '\n', false, "\n"),
+        (&[], '0', true, "\x1bOp"),
+        (&[], '9', true, "\x1bOy"),
+        (&[], '.', true, "\x1bOn"),
+        (&[], ',', true, "\x1bOl"),
+        (&[], '+', true, "\x1bOk"),
+        (&[], '-', true, "\x1bOm"),
+        (&[], '*', true, "\x1bOj"),
+        (&[], '/', true, "\x1bOo"),
+        (&[], '=', true, "\x1bOX"),
+        (&[], '\n', true, "\x1bOM"),
+        (&[CtrlLeft], '+', true, "\x1bO5k"),
+    ];
+
+    #[test]
+    fn keypad() {
+        for &(held, c, keypad_mode, code) in KEYPAD {
+            assert_eq!(encode(&Keypad(c), false, keypad_mode, 0, mods(held)).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        for &(held, ref key, level, code) in OTHER_KEYS {
+            assert_eq!(encode(key, false, false, level, mods(held)).unwrap(), code);
+        }
+    }
+
+}
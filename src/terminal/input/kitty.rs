@@ -0,0 +1,323 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! The progressive enhancement keyboard protocol, as defined by kitty. Programs in ANSI mode
+//! push flags onto a stack with `CSI > flags u` to opt into each enhancement.
+use std::borrow::Cow;
+
+use datatypes::Key;
+use datatypes::Key::*;
+
+use super::modifiers::Modifiers;
+
+pub const DISAMBIGUATE: u32 = 0b1;
+pub const REPORT_EVENTS: u32 = 0b10;
+pub const REPORT_ALTERNATES: u32 = 0b100;
+pub const REPORT_ALL: u32 = 0b1000;
+pub const REPORT_TEXT: u32 = 0b10000;
+
+// The kitty modifier bits for the lock keys, and for the lock keys and shift, which do not
+// change the meaning of a text producing key.
+const LOCKS: u32 = 0b1000000;
+const SHIFT_AND_LOCKS: u32 = 0b1000001;
+
+// The flags stack is bounded; the oldest entries are evicted when it overflows.
+const MAX_STACK: usize = 16;
+
+/// The keyboard enhancement flags stack.
+#[derive(Default)]
+pub struct KittyFlags {
+    stack: Vec<u32>,
+}
+
+impl KittyFlags {
+
+    pub fn current(&self) -> u32 {
+        self.stack.last().map_or(0, |&flags| flags)
+    }
+
+    /// Every entry of the stack, from the bottom.
+    pub fn stack(&self) -> &[u32] {
+        &self.stack
+    }
+
+    pub fn push(&mut self, flags: u32) {
+        if self.stack.len() == MAX_STACK { self.stack.remove(0); }
+        self.stack.push(flags & 0b11111);
+    }
+
+    pub fn pop(&mut self, n: u32) {
+        let len = self.stack.len().saturating_sub(n as usize);
+        self.stack.truncate(len);
+    }
+
+    /// Alter the current flags: mode 1 replaces them, mode 2 sets the given bits and mode 3
+    /// unsets them.
+    pub fn set(&mut self, flags: u32, mode: u32) {
+        let flags = flags & 0b11111;
+        let current = self.current();
+        let flags = match mode {
+            1   => flags,
+            2   => current | flags,
+            3   => current & !flags,
+            _   => return,
+        };
+        match self.stack.last_mut() {
+            Some(top)   => *top = flags,
+            None        => self.stack.push(flags),
+        }
+    }
+
+}
+
+/// Encode a key event under the given enhancement flags. Returns `None` if the key should be
+/// sent using the legacy encoding instead (which sends nothing for releases). `app_mode` is
+/// whether the cursor keys are in application mode (DECCKM).
+pub fn encode(key: &Key, press: bool, repeat: bool, app_mode: bool, flags: u32, mods: Modifiers)
+        -> Option<Cow<'static, str>> {
+    let event = match (press, repeat) {
+        (false, _)      => 3,
+        (true, true)    => 2,
+        (true, false)   => 1,
+    };
+    if event != 1 && flags & REPORT_EVENTS == 0 { return None; }
+    let all = flags & REPORT_ALL != 0;
+    let bits = mods.kitty();
+    let modified = bits & !SHIFT_AND_LOCKS != 0;
+    match *key {
+        Char('\x1b')                        => {
+            if all || flags & DISAMBIGUATE != 0 {
+                Some(sequence(27, None, 'u', bits, event, None))
+            } else { None }
+        }
+        Char('\x09')                        => legacy_control(9, flags, bits, event),
+        Char('\x7f')                        => legacy_control(127, flags, bits, event),
+        Enter                               => legacy_control(13, flags, bits, event),
+        Backspace                           => legacy_control(127, flags, bits, event),
+        Char(c) if c < '\x20'               => None,
+        Char(c)                             => {
+            if !(all || event == 3 || (flags & DISAMBIGUATE != 0 && modified)) {
+                return None;
+            }
+            let base = c.to_lowercase().next().unwrap_or(c);
+            let shifted = if base != c && flags & REPORT_ALTERNATES != 0 { Some(c) } else { None };
+            let text = if all && flags & REPORT_TEXT != 0 && event != 3 && !modified {
+                Some(c)
+            } else { None };
+            Some(sequence(base as u32, shifted, 'u', bits, event, text))
+        }
+        UpArrow                             => Some(cursor_key('A', app_mode && !all, bits, event)),
+        DownArrow                           => Some(cursor_key('B', app_mode && !all, bits, event)),
+        RightArrow                          => Some(cursor_key('C', app_mode && !all, bits, event)),
+        LeftArrow                           => Some(cursor_key('D', app_mode && !all, bits, event)),
+        End                                 => Some(cursor_key('F', app_mode && !all, bits, event)),
+        Home                                => Some(cursor_key('H', app_mode && !all, bits, event)),
+        Insert                              => Some(sequence(2, None, '~', bits, event, None)),
+        Delete                              => Some(sequence(3, None, '~', bits, event, None)),
+        PageUp                              => Some(sequence(5, None, '~', bits, event, None)),
+        PageDown                            => Some(sequence(6, None, '~', bits, event, None)),
+        Function(n)                         => {
+            let (code, term) = match n {
+                1           => (1, 'P'),
+                2           => (1, 'Q'),
+                3           => (13, '~'),
+                4           => (1, 'S'),
+                5           => (15, '~'),
+                6...10      => (n as u32 + 11, '~'),
+                11...12     => (n as u32 + 12, '~'),
+                13...35     => (57376 + n as u32 - 13, 'u'),
+                _           => return None,
+            };
+            Some(sequence(code, None, term, bits, event, None))
+        }
+        Keypad(c) if all || flags & DISAMBIGUATE != 0 => {
+            let code = match c {
+                '0'...'9'   => 57399 + (c as u32 - '0' as u32),
+                '.'         => 57409,
+                '/'         => 57410,
+                '*'         => 57411,
+                '-'         => 57412,
+                '+'         => 57413,
+                '\n'        => 57414,
+                '='         => 57415,
+                ','         => 57416,
+                _           => return None,
+            };
+            Some(sequence(code, None, 'u', bits, event, None))
+        }
+        _ if !all                           => None,
+        CapsLock                            => Some(sequence(57358, None, 'u', bits, event, None)),
+        ScrollLock                          => Some(sequence(57359, None, 'u', bits, event, None)),
+        NumLock                             => Some(sequence(57360, None, 'u', bits, event, None)),
+        Menu                                => Some(sequence(57363, None, 'u', bits, event, None)),
+        ShiftLeft                           => Some(sequence(57441, None, 'u', bits, event, None)),
+        CtrlLeft                            => Some(sequence(57442, None, 'u', bits, event, None)),
+        AltLeft                             => Some(sequence(57443, None, 'u', bits, event, None)),
+        Super                               => Some(sequence(57444, None, 'u', bits, event, None)),
+        Hyper                               => Some(sequence(57445, None, 'u', bits, event, None)),
+        Meta                                => Some(sequence(57446, None, 'u', bits, event, None)),
+        ShiftRight                          => Some(sequence(57447, None, 'u', bits, event, None)),
+        CtrlRight                           => Some(sequence(57448, None, 'u', bits, event, None)),
+        AltGr                               => Some(sequence(57453, None, 'u', bits, event, None)),
+        Keypad(_) | Cmd(_) | MenuSelection(_) => None,
+    }
+}
+
+// Enter, Tab and Backspace keep their legacy encoding unless they are modified, and their
+// releases are only reported when all keys are reported as escape codes.
+fn legacy_control(code: u32, flags: u32, bits: u32, event: u32) -> Option<Cow<'static, str>> {
+    if flags & REPORT_ALL != 0 || (flags & DISAMBIGUATE != 0 && bits & !LOCKS != 0) {
+        Some(sequence(code, None, 'u', bits, event, None))
+    } else { None }
+}
+
+// The cursor keys keep the SS3 encoding of application mode when they are pressed unmodified,
+// unless all keys are reported as escape codes.
+fn cursor_key(term: char, app_mode: bool, bits: u32, event: u32) -> Cow<'static, str> {
+    if app_mode && bits == 0 && event == 1 {
+        Cow::Owned(format!("\x1bO{}", term))
+    } else {
+        sequence(1, None, term, bits, event, None)
+    }
+}
+
+fn sequence(code: u32, shifted: Option<char>, term: char, bits: u32, event: u32,
+            text: Option<char>) -> Cow<'static, str> {
+    let mut key = code.to_string();
+    if let Some(c) = shifted {
+        key.push_str(&format!(":{}", c as u32));
+    }
+    let mods = match (bits, event, text) {
+        (0, 1, None)    => String::new(),
+        (_, 1, _)       => (bits + 1).to_string(),
+        (_, _, _)       => format!("{}:{}", bits + 1, event),
+    };
+    Cow::Owned(match text {
+        Some(c)                                 => {
+            format!("\x1b[{};{};{}{}", key, mods, c as u32, term)
+        }
+        None if mods.is_empty() && code == 1    => format!("\x1b[{}", term),
+        None if mods.is_empty()                 => format!("\x1b[{}{}", key, term),
+        None                                    => format!("\x1b[{};{}{}", key, mods, term),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::Key;
+    use datatypes::Key::*;
+    use terminal::input::modifiers::Modifiers;
+
+    use super::*;
+
+    fn mods(keys: &[Key]) -> Modifiers {
+        let mut mods = Modifiers::new();
+        for key in keys { mods.apply(key, true); }
+        mods
+    }
+
+    fn press(key: Key, flags: u32, held: &[Key]) -> Option<String> {
+        encode(&key, true, false, false, flags, mods(held)).map(|s| s.into_owned())
+    }
+
+    fn press_app(key: Key, flags: u32, held: &[Key]) -> Option<String> {
+        encode(&key, true, false, true, flags, mods(held)).map(|s| s.into_owned())
+    }
+
+    #[test]
+    fn disambiguate() {
+        assert_eq!(press(Char('a'), DISAMBIGUATE, &[]), None);
+        assert_eq!(press(Char('A'), DISAMBIGUATE, &[ShiftLeft]), None);
+        assert_eq!(press(Char('i'), DISAMBIGUATE, &[CtrlLeft]),
+                   Some(String::from("\x1b[105;5u")));
+        assert_eq!(press(Char('\x09'), DISAMBIGUATE, &[]), None);
+        assert_eq!(press(Char('a'), DISAMBIGUATE, &[CtrlLeft, ShiftLeft]),
+                   Some(String::from("\x1b[97;6u")));
+        assert_eq!(press(Char('a'), DISAMBIGUATE, &[Super]), Some(String::from("\x1b[97;9u")));
+        assert_eq!(press(Char('\x1b'), DISAMBIGUATE, &[]), Some(String::from("\x1b[27u")));
+        assert_eq!(press(Enter, DISAMBIGUATE, &[]), None);
+        assert_eq!(press(Enter, DISAMBIGUATE, &[ShiftLeft]), Some(String::from("\x1b[13;2u")));
+        assert_eq!(press(UpArrow, DISAMBIGUATE, &[]), Some(String::from("\x1b[A")));
+        assert_eq!(press(UpArrow, DISAMBIGUATE, &[AltLeft]), Some(String::from("\x1b[1;3A")));
+        assert_eq!(press(Delete, DISAMBIGUATE, &[]), Some(String::from("\x1b[3~")));
+        assert_eq!(press(Function(3), DISAMBIGUATE, &[]), Some(String::from("\x1b[13~")));
+        assert_eq!(press(Function(13), DISAMBIGUATE, &[]), Some(String::from("\x1b[57376u")));
+        assert_eq!(press(Keypad('5'), DISAMBIGUATE, &[]), Some(String::from("\x1b[57404u")));
+        assert_eq!(press(Keypad('\n'), 0, &[]), None);
+    }
+
+    #[test]
+    fn events() {
+        let flags = DISAMBIGUATE | REPORT_EVENTS;
+        assert_eq!(encode(&Char('a'), true, true, false, flags, mods(&[])), None);
+        assert_eq!(encode(&Char('a'), false, false, false, flags, mods(&[])).unwrap(),
+                   "\x1b[97;1:3u");
+        assert_eq!(encode(&UpArrow, true, true, false, flags, mods(&[])).unwrap(), "\x1b[1;1:2A");
+        assert_eq!(encode(&UpArrow, false, false, false, flags, mods(&[CtrlLeft])).unwrap(),
+                   "\x1b[1;5:3A");
+        assert_eq!(encode(&Enter, false, false, false, flags, mods(&[])), None);
+        assert_eq!(encode(&Char('a'), false, false, false, DISAMBIGUATE, mods(&[])), None);
+    }
+
+    #[test]
+    fn cursor_key_mode() {
+        assert_eq!(press_app(UpArrow, DISAMBIGUATE, &[]), Some(String::from("\x1bOA")));
+        assert_eq!(press_app(Home, DISAMBIGUATE, &[]), Some(String::from("\x1bOH")));
+        assert_eq!(press_app(UpArrow, DISAMBIGUATE, &[CtrlLeft]), Some(String::from("\x1b[1;5A")));
+        assert_eq!(press_app(UpArrow, REPORT_ALL, &[]), Some(String::from("\x1b[A")));
+        assert_eq!(encode(&UpArrow, false, false, true, DISAMBIGUATE | REPORT_EVENTS, mods(&[]))
+                       .unwrap(), "\x1b[1;1:3A");
+    }
+
+    #[test]
+    fn all_keys() {
+        let flags = REPORT_ALL | REPORT_EVENTS;
+        assert_eq!(press(Char('a'), flags, &[]), Some(String::from("\x1b[97u")));
+        assert_eq!(press(Enter, flags, &[]), Some(String::from("\x1b[13u")));
+        assert_eq!(press(ShiftLeft, flags, &[ShiftLeft]), Some(String::from("\x1b[57441;2u")));
+        assert_eq!(encode(&Hyper, false, false, false, flags, mods(&[])).unwrap(),
+                   "\x1b[57445;1:3u");
+        assert_eq!(press(Char('a'), REPORT_ALL, &[Meta, CapsLock]),
+                   Some(String::from("\x1b[97;97u")));
+    }
+
+    #[test]
+    fn alternates_and_text() {
+        let flags = REPORT_ALL | REPORT_ALTERNATES | REPORT_TEXT;
+        assert_eq!(press(Char('A'), flags, &[ShiftLeft]),
+                   Some(String::from("\x1b[97:65;2;65u")));
+        assert_eq!(press(Char('a'), flags, &[]), Some(String::from("\x1b[97;1;97u")));
+        assert_eq!(press(Char('a'), flags, &[CtrlLeft]), Some(String::from("\x1b[97;5u")));
+    }
+
+    #[test]
+    fn flags_stack() {
+        let mut flags = KittyFlags::default();
+        assert_eq!(flags.current(), 0);
+        flags.push(DISAMBIGUATE);
+        flags.push(REPORT_ALL);
+        assert_eq!(flags.current(), REPORT_ALL);
+        flags.set(REPORT_EVENTS, 2);
+        assert_eq!(flags.current(), REPORT_ALL | REPORT_EVENTS);
+        flags.set(REPORT_ALL, 3);
+        assert_eq!(flags.current(), REPORT_EVENTS);
+        flags.pop(1);
+        assert_eq!(flags.current(), DISAMBIGUATE);
+        flags.pop(5);
+        assert_eq!(flags.current(), 0);
+    }
+
+}
@@ -14,24 +14,37 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use std::io::{self, Write};
+use std::mem;
 
 use command::Command;
 use datatypes::{BufferSettings, EchoSettings, InputMode, Key};
 use datatypes::InputMode::*;
+use datatypes::args::Argument;
+
+use super::state::{InputState, StateError};
 
 mod buffer;
 mod ansi;
 mod echo;
+mod kitty;
 mod modifiers;
 mod notty;
 
 use self::buffer::InputBuffer;
+use self::kitty::KittyFlags;
 use self::modifiers::Modifiers;
 
 pub struct Input {
     tty: Box<Write>,
     mode: InputMode,
     modifiers: Modifiers,
+    kitty: KittyFlags,
+    kitty_beneath: Vec<KittyFlags>,
+    other_keys: u32,
+    keypad: bool,
+    notty_flags: u32,
+    focus_events: bool,
+    held: Option<Key>,
     buffer: InputBuffer,
     echo_set: Option<EchoSettings>,
     buffer_set: Option<BufferSettings>,
@@ -44,12 +57,23 @@ impl Input {
             tty: Box::new(tty),
             mode: InputMode::Ansi(false),
             modifiers: Modifiers::new(),
+            kitty: KittyFlags::default(),
+            kitty_beneath: Vec::new(),
+            other_keys: 0,
+            keypad: false,
+            notty_flags: 0,
+            focus_events: false,
+            held: None,
             buffer: InputBuffer::default(),
             echo_set: None,
             buffer_set: None,
         }
     }
 
+    pub fn mode(&self) -> InputMode {
+        self.mode
+    }
+
     pub fn set_mode(&mut self, mode: InputMode) {
         self.mode = mode;
     }
@@ -62,22 +86,118 @@ impl Input {
         self.buffer_set = buffer;
     }
 
-    pub fn write(&mut self, mut key: Key, press: bool) -> io::Result<Option<Box<Command>>> {
+    pub fn keyboard_flags(&self) -> u32 {
+        self.kitty.current()
+    }
+
+    pub fn push_keyboard_flags(&mut self, flags: u32) {
+        self.kitty.push(flags);
+    }
+
+    pub fn pop_keyboard_flags(&mut self, n: u32) {
+        self.kitty.pop(n);
+    }
+
+    pub fn set_keyboard_flags(&mut self, flags: u32, mode: u32) {
+        self.kitty.set(flags, mode);
+    }
+
+    /// Start an empty keyboard flags stack for a buffer pushed over the active one. Each buffer
+    /// keeps its own stack, so that the alternate screen does not disturb the main screen's.
+    pub fn push_buffer(&mut self) {
+        let kitty = mem::replace(&mut self.kitty, KittyFlags::default());
+        self.kitty_beneath.push(kitty);
+    }
+
+    /// Return to the keyboard flags stack of the buffer beneath the active one.
+    pub fn pop_buffer(&mut self) {
+        if let Some(kitty) = self.kitty_beneath.pop() { self.kitty = kitty; }
+    }
+
+    pub fn set_modify_other_keys(&mut self, level: u32) {
+        self.other_keys = level;
+    }
+
+    pub fn set_keypad_mode(&mut self, app_mode: bool) {
+        self.keypad = app_mode;
+    }
+
+    pub fn set_notty_flags(&mut self, flags: u32) {
+        self.notty_flags = flags;
+    }
+
+    pub fn set_focus_reporting(&mut self, report: bool) {
+        self.focus_events = report;
+    }
+
+    pub fn focus_reporting(&self) -> bool {
+        self.focus_events
+    }
+
+    /// Save the modes which determine what is sent to the controlling process. Keys being held
+    /// and buffered input are not saved.
+    pub fn state(&self) -> InputState {
+        InputState {
+            mode: self.mode.encode(),
+            keyboard_flags: self.kitty.stack().to_vec(),
+            inactive_keyboard_flags: self.kitty_beneath.iter().map(|kitty| {
+                kitty.stack().to_vec()
+            }).collect(),
+            modify_other_keys: self.other_keys,
+            keypad: self.keypad,
+            notty_flags: self.notty_flags,
+            focus_events: self.focus_events,
+            echo: self.echo_set.map(|echo| echo.encode()),
+            buffer: self.buffer_set.map(|buffer| buffer.encode()),
+        }
+    }
+
+    pub fn restore(&mut self, state: &InputState) -> Result<(), StateError> {
+        self.mode = try!(decode(&state.mode));
+        self.kitty = stack(&state.keyboard_flags);
+        self.kitty_beneath = state.inactive_keyboard_flags.iter().map(|flags| stack(flags))
+                                                                .collect();
+        self.other_keys = state.modify_other_keys;
+        self.keypad = state.keypad;
+        self.notty_flags = state.notty_flags;
+        self.focus_events = state.focus_events;
+        self.echo_set = match state.echo {
+            Some(ref echo)  => Some(try!(decode(echo))),
+            None            => None,
+        };
+        self.buffer_set = match state.buffer {
+            Some(ref buffer)    => Some(try!(decode(buffer))),
+            None                => None,
+        };
+        Ok(())
+    }
+
+    pub fn focus(&mut self, focused: bool) -> io::Result<()> {
+        if !self.focus_events { return Ok(()); }
+        let code = match (self.mode, focused) {
+            (InputMode::Ansi(_), true)      => "\x1b[I",
+            (InputMode::Ansi(_), false)     => "\x1b[O",
+            (InputMode::Notty(_), focused)  => notty::focus(focused),
+        };
+        self.tty.write_all(code.as_bytes())
+    }
+
+    pub fn write(&mut self, key: Key, press: bool) -> io::Result<Option<Box<Command>>> {
         if key.is_modifier() {
             self.modifiers.apply(&key, press);
         }
-        if self.modifiers.ctrl() {
-            key = key.ctrl_modify();
-        }
+        let repeat = self.repeat(&key, press);
         match (self.buffer_set, self.echo_set) {
             (Some(buffer), Some(echo)) if press => {
+               let key = self.ctrl_modify(&key);
                if let Some(data) = self.buffer.write(&key, buffer, echo) {
                    try!(self.tty.write_all(data.as_bytes()));
                }
             }
-            (None, _)   => try!(self.send(&key, press)),
+            (None, _)   => try!(self.send(&key, press, repeat)),
             _           => ()
         }
+        let key = self.ctrl_modify(&key);
         match self.echo_set {
             Some(set) if press  => {
                 Ok(echo::encode(key, set.lerase as char, set.lnext as char, set.werase as char))
@@ -86,22 +206,78 @@ impl Input {
         }
     }
 
-    fn send(&mut self, key: &Key, press: bool) -> io::Result<()> {
+    // A press of the key which is already held down is an autorepeat.
+    fn repeat(&mut self, key: &Key, press: bool) -> bool {
+        match *key {
+            Key::Cmd(_) | Key::MenuSelection(_)     => return false,
+            _ if key.is_modifier()                  => return false,
+            _                                       => (),
+        }
+        match (press, self.held.as_ref() == Some(key)) {
+            (true, repeat)  => {
+                self.held = Some(key.clone());
+                repeat
+            }
+            (false, true)   => {
+                self.held = None;
+                false
+            }
+            (false, false)  => false,
+        }
+    }
+
+    fn ctrl_modify(&self, key: &Key) -> Key {
+        if self.modifiers.ctrl() { key.clone().ctrl_modify() } else { key.clone() }
+    }
+
+    fn send(&mut self, key: &Key, press: bool, repeat: bool) -> io::Result<()> {
         match self.mode {
+            InputMode::Ansi(_) if self.kitty.current() != 0 => {
+                let flags = self.kitty.current();
+                let app_mode = self.cursor_app_mode();
+                match kitty::encode(key, press, repeat, app_mode, flags, self.modifiers) {
+                    Some(code)                  => self.tty.write_all(code.as_bytes()),
+                    None if key.is_modifier()   => Ok(()),
+                    None if press               => self.send_ansi(key),
+                    None                        => Ok(()),
+                }
+            }
             InputMode::Ansi(_) if key.is_modifier()     => {
                 Ok(())
             }
-            InputMode::Ansi(app_mode) if press          => {
-                match ansi::encode(key, app_mode, self.modifiers) {
-                    Some(code)  => self.tty.write_all(code.as_bytes()),
-                    None        => Ok(()),
-                }
-            }
+            InputMode::Ansi(_) if press                 => self.send_ansi(key),
             InputMode::Ansi(_)                          => Ok(()),
-            InputMode::Notty(flags)                     => {
-                self.tty.write_all(notty::encode(key, press, flags, self.modifiers).as_bytes())
+            InputMode::Notty(_)                         => {
+                let key = self.ctrl_modify(key);
+                let code = notty::encode(&key, press, self.notty_flags, self.modifiers);
+                self.tty.write_all(code.as_bytes())
             }
         }
     }
 
+    // Whether the cursor keys are in application mode (DECCKM).
+    fn cursor_app_mode(&self) -> bool {
+        if let InputMode::Ansi(app_mode) = self.mode { app_mode } else { false }
+    }
+
+    fn send_ansi(&mut self, key: &Key) -> io::Result<()> {
+        let app_mode = self.cursor_app_mode();
+        match ansi::encode(key, app_mode, self.keypad, self.other_keys, self.modifiers) {
+            Some(code)  => self.tty.write_all(code.as_bytes()),
+            None        => Ok(()),
+        }
+    }
+
+}
+
+// Rebuild a keyboard flags stack from its entries, from the bottom.
+fn stack(entries: &[u32]) -> KittyFlags {
+    let mut kitty = KittyFlags::default();
+    for &flags in entries { kitty.push(flags); }
+    kitty
+}
+
+// Decode a mode saved in the notty argument encoding.
+fn decode<T: Argument>(arg: &str) -> Result<T, StateError> {
+    T::decode(Some(arg), None).ok_or(StateError::Invalid("unknown input mode"))
 }
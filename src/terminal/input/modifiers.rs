@@ -25,6 +25,9 @@ pub struct Modifiers {
     rctrl: bool,
     lalt: bool,
     ralt: bool,
+    meta: bool,
+    superkey: bool,
+    hyper: bool,
 }
 
 impl Modifiers {
@@ -37,6 +40,9 @@ impl Modifiers {
             rctrl: false,
             lalt: false,
             ralt: false,
+            meta: false,
+            superkey: false,
+            hyper: false,
         }
     }
 
@@ -52,10 +58,32 @@ impl Modifiers {
         self.lalt || self.ralt
     }
 
+    pub fn super_key(&self) -> bool {
+        self.superkey
+    }
+
+    pub fn hyper(&self) -> bool {
+        self.hyper
+    }
+
     pub fn triplet(&self) -> (bool, bool, bool) {
         (self.shift(), self.ctrl(), self.alt())
     }
 
+    /// The modifier bit field used by the kitty keyboard protocol. Unlike `shift`, the shift bit
+    /// reports the state of the shift keys themselves; caps lock is reported separately.
+    pub fn kitty(&self) -> u32 {
+        let mut bits = 0;
+        if self.lshift || self.rshift   { bits |= 0b1; }
+        if self.alt()                   { bits |= 0b10; }
+        if self.ctrl()                  { bits |= 0b100; }
+        if self.superkey                { bits |= 0b1000; }
+        if self.hyper                   { bits |= 0b10000; }
+        if self.meta                    { bits |= 0b100000; }
+        if self.caps                    { bits |= 0b1000000; }
+        bits
+    }
+
     pub fn apply(&mut self, key: &Key, press: bool) {
         match *key {
             ShiftLeft           => self.lshift = press,
@@ -64,7 +92,11 @@ impl Modifiers {
             CtrlRight           => self.rctrl = press,
             AltLeft             => self.lalt = press,
             AltGr               => self.ralt = press,
+            Meta                => self.meta = press,
+            Super               => self.superkey = press,
+            Hyper               => self.hyper = press,
             CapsLock if press   => self.caps = !self.caps,
+            CapsLock            => (),
             _                   => unreachable!(),
         }
     }
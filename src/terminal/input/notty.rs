@@ -19,8 +19,16 @@ use datatypes::Key;
 use datatypes::Key::*;
 use super::modifiers::Modifiers;
 
+/// Report presses and releases of the caps lock, num lock and scroll lock keys.
+pub const REPORT_LOCKS: u32 = 1;
+/// Report keys on the numeric keypad with their own key codes instead of as characters.
+pub const REPORT_KEYPAD: u32 = 2;
+
 macro_rules! key {
     ($k:expr, $press:expr, $mods:expr) =>(match ($mods.triplet(), $press) {
+        _ if $mods.super_key() || $mods.hyper() => {
+            Cow::Owned(format!(concat!("\x1b{{{:x};",$k,"}}"), modifier_code($press, $mods)))
+        }
         ((false, false, false), false)  => Cow::Borrowed(concat!("\x1b{0;",$k,"}")),
         ((false, false, false), true)   => Cow::Borrowed(concat!("\x1b{1;",$k,"}")),
         ((false, false, true),  false)  => Cow::Borrowed(concat!("\x1b{2;",$k,"}")),
@@ -40,7 +48,7 @@ macro_rules! key {
     });
 }
 
-pub fn encode(key: &Key, press: bool, _: (), mods: Modifiers) -> Cow<'static, str> {
+pub fn encode(key: &Key, press: bool, flags: u32, mods: Modifiers) -> Cow<'static, str> {
     match *key {
         Char(c)             => char_key(c, press, mods),
         Enter               => char_key('\n', press, mods),
@@ -63,17 +71,42 @@ pub fn encode(key: &Key, press: bool, _: (), mods: Modifiers) -> Cow<'static, st
         AltGr               => key!("d", press, mods),
         Meta                => key!("e", press, mods),
         Menu                => key!("f", press, mods),
-        NumLock             => unimplemented!(),
-        ScrollLock          => unimplemented!(),
-        CapsLock            => unimplemented!(), 
-        Function(_)         => unimplemented!(),
+        Super               => key!("10", press, mods),
+        Hyper               => key!("11", press, mods),
+        CapsLock | NumLock | ScrollLock if flags & REPORT_LOCKS == 0 => Cow::Borrowed(""),
+        CapsLock            => key!("12", press, mods),
+        NumLock             => key!("13", press, mods),
+        ScrollLock          => key!("14", press, mods),
+        Function(n)         => {
+            Cow::Owned(format!("\x1b{{{:x};{:x}}}", modifier_code(press, mods), 0x20 + n as u32))
+        }
+        Keypad(c) if flags & REPORT_KEYPAD == 0 => char_key(c, press, mods),
+        Keypad(c)           => {
+            Cow::Owned(format!("\x1b{{{:x};15;{:x}}}", modifier_code(press, mods), c as u32))
+        }
         Cmd(ref s)          => s.clone(),
-        MenuSelection(_)    => unimplemented!(),
+        MenuSelection(n)    => {
+            Cow::Owned(format!("\x1b{{{:x};16;{:x}}}", modifier_code(press, mods), n))
+        }
     }
 }
 
+pub fn focus(focused: bool) -> &'static str {
+    if focused { "\x1b{1;17}" } else { "\x1b{0;17}" }
+}
+
+// The leading component of a key code: a bit each for press, alt, ctrl, shift, super and hyper.
+fn modifier_code(press: bool, mods: Modifiers) -> u8 {
+    let (shift, ctrl, alt) = mods.triplet();
+    press as u8 | (alt as u8) << 1 | (ctrl as u8) << 2 | (shift as u8) << 3
+        | (mods.super_key() as u8) << 4 | (mods.hyper() as u8) << 5
+}
+
 fn char_key(c: char, press: bool, mods: Modifiers) -> Cow<'static, str> {
     match (mods.triplet(), press) {
+        _ if mods.super_key() || mods.hyper() => {
+            Cow::Owned(format!("\x1b{{{:x}{{{}}}", modifier_code(press, mods), c))
+        }
         ((_,     _,     false), false)  => Cow::Owned(c.to_string()),
         ((false, false, false), true)   => Cow::Owned(format!("\x1b{{1{{{}}}", c)),
         ((false, false, true),  false)  => Cow::Owned(format!("\x1b{{2{{{}}}", c)),
@@ -89,3 +122,64 @@ fn char_key(c: char, press: bool, mods: Modifiers) -> Cow<'static, str> {
         ((true,  true,  true),  true)   => Cow::Owned(format!("\x1b{{f{{{}}}", c)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::Key;
+    use datatypes::Key::*;
+    use terminal::input::modifiers::Modifiers;
+
+    use super::*;
+
+    fn encode_key(key: Key, press: bool, flags: u32, held: &[Key]) -> String {
+        let mut mods = Modifiers::new();
+        for key in held { mods.apply(key, true); }
+        encode(&key, press, flags, mods).into_owned()
+    }
+
+    #[test]
+    fn locks() {
+        assert_eq!(encode_key(CapsLock, true, 0, &[]), "");
+        assert_eq!(encode_key(NumLock, false, 0, &[]), "");
+        assert_eq!(encode_key(CapsLock, true, REPORT_LOCKS, &[]), "\x1b{1;12}");
+        assert_eq!(encode_key(NumLock, false, REPORT_LOCKS, &[]), "\x1b{0;13}");
+        assert_eq!(encode_key(ScrollLock, true, REPORT_LOCKS, &[ShiftLeft]), "\x1b{9;14}");
+    }
+
+    #[test]
+    fn function_keys() {
+        assert_eq!(encode_key(Function(1), true, 0, &[]), "\x1b{1;21}");
+        assert_eq!(encode_key(Function(12), false, 0, &[]), "\x1b{0;2c}");
+        assert_eq!(encode_key(Function(24), true, 0, &[CtrlLeft]), "\x1b{5;38}");
+    }
+
+    #[test]
+    fn keypad() {
+        assert_eq!(encode_key(Keypad('5'), true, 0, &[]), "\x1b{1{5}");
+        assert_eq!(encode_key(Keypad('5'), true, REPORT_KEYPAD, &[]), "\x1b{1;15;35}");
+        assert_eq!(encode_key(Keypad('\n'), false, REPORT_KEYPAD, &[AltLeft]), "\x1b{2;15;a}");
+    }
+
+    #[test]
+    fn menu_selection() {
+        assert_eq!(encode_key(MenuSelection(0), true, 0, &[]), "\x1b{1;16;0}");
+        assert_eq!(encode_key(MenuSelection(26), true, 0, &[]), "\x1b{1;16;1a}");
+    }
+
+    #[test]
+    fn super_and_hyper() {
+        assert_eq!(encode_key(Char('a'), true, 0, &[Super]), "\x1b{11{a}");
+        assert_eq!(encode_key(Char('a'), false, 0, &[Hyper]), "\x1b{20{a}");
+        assert_eq!(encode_key(UpArrow, true, 0, &[Super, CtrlLeft]), "\x1b{15;1}");
+        assert_eq!(encode_key(Function(5), false, 0, &[Super, Hyper]), "\x1b{30;25}");
+        assert_eq!(encode_key(Keypad('1'), true, REPORT_KEYPAD, &[Hyper]), "\x1b{21;15;31}");
+    }
+
+    #[test]
+    fn focus_events() {
+        assert_eq!(focus(true), "\x1b{1;17}");
+        assert_eq!(focus(false), "\x1b{0;17}");
+    }
+
+}
@@ -16,41 +16,86 @@
 use std::io::{self, Write};
 use std::mem;
 use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
 
 mod char_grid;
+mod diagnostics;
+mod events;
 mod input;
+mod state;
 
-use datatypes::{BufferSettings, EchoSettings, InputMode, Key};
+use datatypes::{BufferSettings, Coords, EchoSettings, InputMode, Key};
 
-pub use self::char_grid::{CharCell, CharGrid, Cursor, Grid, Styles, Tooltip};
+pub use self::char_grid::{CharCell, CharGrid, Cursor, Damage, Grid, Scrollback, Styles, Tooltip};
+pub use self::char_grid::{SearchMatch, SearchPattern, Selection, SelectionMode, TextFormat};
+pub use self::char_grid::ViewCells;
+pub use self::char_grid::{CellState, CursorState, GridState, RowState, StateError, StylesState};
+pub use self::diagnostics::{DiagnosticSink, Diagnostics, LogFile, NoDiagnostics, Problem};
+pub use self::diagnostics::Severity;
+pub use self::events::{Mode, NoEvents, TerminalEvents};
+pub use self::state::{InputState, TerminalState, STATE_VERSION};
 
 use self::input::Input;
 
+/// How long a synchronized update may hold back rendering before it is treated as finished, in
+/// case the application never ends it.
+pub const SYNC_TIMEOUT_MS: u64 = 150;
+
 pub struct Terminal {
     pub width: u32,
     pub height: u32,
     title: String,
+    cwd: Option<String>,
     active: CharGrid,
     inactive: Vec<CharGrid>,
+    scrollback: Scrollback,
+    focused: bool,
+    sync_update: Option<Instant>,
     tty: Input,
+    events: Box<TerminalEvents>,
+    diagnostics: Diagnostics,
 }
 
 impl Terminal {
 
-    pub fn new<W: Write + 'static>(width: u32, height: u32, tty: W) -> Terminal {
-        let grid = CharGrid::new(width, height, false, true);
+    /// Create a terminal which writes input for the controlling process to `tty`, and passes
+    /// the side effects of its output to `events`.
+    pub fn new<W, E>(width: u32, height: u32, tty: W, events: E) -> Terminal
+            where W: Write + 'static, E: TerminalEvents + 'static {
+        Terminal::with_scrollback(width, height, Scrollback::default(), tty, events)
+    }
+
+    /// Create a terminal which keeps the given scrollback, in its primary buffer and in every
+    /// buffer pushed later which scrolls vertically.
+    pub fn with_scrollback<W, E>(width: u32, height: u32, scrollback: Scrollback, tty: W,
+                                 events: E) -> Terminal
+            where W: Write + 'static, E: TerminalEvents + 'static {
+        let grid = CharGrid::with_scrollback(width, height, false, Some(scrollback));
         let tty = Input::new(tty);
         Terminal {
             width: width,
             height: height,
             title: String::new(),
+            cwd: None,
             active: grid,
             inactive: Vec::new(),
+            scrollback: scrollback,
+            focused: true,
+            sync_update: None,
             tty: tty,
+            events: Box::new(events),
+            diagnostics: Diagnostics::default(),
         }
     }
 
     pub fn send_input(&mut self, key: Key, press: bool) -> io::Result<()> {
+        // A key the user presses returns the view to the bottom of the grid, but a response the
+        // terminal sends to a query does not.
+        match key {
+            Key::Cmd(_)                         => (),
+            _ if press && !key.is_modifier()    => self.scroll_view_to_bottom(),
+            _                                   => (),
+        }
         if let Some(cmd) = try!(match key {
             Key::DownArrow | Key::UpArrow | Key::Enter if press => {
                 let cursor = self.cursor_position();
@@ -70,22 +115,128 @@ impl Terminal {
         Ok(())
     }
 
+    /// Record whether the terminal has input focus, reporting the change to the controlling
+    /// process if it has asked for focus events.
+    pub fn set_focused(&mut self, focused: bool) -> io::Result<()> {
+        if self.focused == focused { return Ok(()); }
+        self.focused = focused;
+        self.active.damage_all();
+        self.tty.focus(focused)
+    }
+
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Begin or end a synchronized update. Beginning an update while one is already in progress
+    /// restarts its timeout.
+    pub fn set_synchronized_update(&mut self, on: bool) {
+        self.sync_update = if on { Some(Instant::now()) } else { None };
+        self.events.mode_changed(Mode::SynchronizedUpdate(on));
+    }
+
+    /// Whether the application has begun a synchronized update which it has not ended.
+    pub fn synchronized_update(&self) -> bool {
+        self.sync_update.is_some()
+    }
+
+    /// Whether rendering should be held back because a synchronized update is in progress and
+    /// has not yet timed out.
+    pub fn update_in_progress(&self) -> bool {
+        self.update_in_progress_at(Instant::now())
+    }
+
+    /// Whether rendering should be held back at the instant `now`, which must not be earlier
+    /// than the beginning of the update.
+    pub fn update_in_progress_at(&self, now: Instant) -> bool {
+        self.sync_update.map_or(false, |start| {
+            now.duration_since(start) < Duration::from_millis(SYNC_TIMEOUT_MS)
+        })
+    }
+
     pub fn push_buffer(&mut self, scroll_x: bool, scroll_y: bool) {
-        let mut grid = CharGrid::new(self.width, self.height, scroll_x, scroll_y);
+        let scrollback = if scroll_y { Some(self.scrollback) } else { None };
+        self.push_buffer_with_scrollback(scroll_x, scrollback);
+    }
+
+    /// Push a buffer which keeps its own amount of scrollback, or which does not scroll
+    /// vertically if `scrollback` is `None`.
+    pub fn push_buffer_with_scrollback(&mut self, scroll_x: bool, scrollback: Option<Scrollback>) {
+        let mut grid = CharGrid::with_scrollback(self.width, self.height, scroll_x, scrollback);
         mem::swap(&mut grid, &mut self.active);
         self.inactive.push(grid);
+        self.tty.push_buffer();
+        self.keyboard_flags_changed();
+    }
+
+    /// Set the scrollback kept by every buffer which scrolls vertically, and by those pushed
+    /// later.
+    pub fn set_scrollback(&mut self, scrollback: Scrollback) {
+        self.scrollback = scrollback;
+        self.active.set_scrollback(scrollback);
+        for grid in &mut self.inactive { grid.set_scrollback(scrollback); }
+    }
+
+    /// Whether a buffer has been pushed over the primary buffer, as by the alternate screen.
+    pub fn buffer_pushed(&self) -> bool {
+        !self.inactive.is_empty()
     }
 
     pub fn pop_buffer(&mut self) {
-        self.inactive.pop().map(|grid| self.active = grid);
+        if let Some(grid) = self.inactive.pop() {
+            self.active = grid;
+            self.active.damage_all();
+            self.tty.pop_buffer();
+            self.keyboard_flags_changed();
+        }
     }
 
     pub fn set_title(&mut self, title: String) {
         self.title = title;
+        self.events.title_changed(&self.title);
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Record the current working directory reported by the controlling process.
+    pub fn set_cwd(&mut self, cwd: String) {
+        self.events.cwd_changed(&cwd);
+        self.cwd = Some(cwd);
+    }
+
+    /// The last working directory reported by the controlling process, if it has reported one.
+    pub fn cwd(&self) -> Option<&str> {
+        self.cwd.as_ref().map(|cwd| &cwd[..])
+    }
+
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.events.set_clipboard(text);
+    }
+
+    pub fn request_resize(&mut self, cols: u32, rows: u32) {
+        self.events.resize_requested(cols, rows);
+    }
+
+    /// Activate the hyperlink on a cell of the grid, if there is one. Returns whether there was.
+    pub fn activate_hyperlink(&mut self, coords: Coords) -> bool {
+        match self.active.hyperlink_at(coords).map(String::from) {
+            Some(uri)   => {
+                self.events.hyperlink_activated(&uri);
+                true
+            }
+            None        => false,
+        }
+    }
+
+    pub fn input_mode(&self) -> InputMode {
+        self.tty.mode()
     }
 
     pub fn set_input_mode(&mut self, mode: InputMode) {
         self.tty.set_mode(mode);
+        self.events.mode_changed(Mode::Input(mode));
     }
 
     pub fn set_buffer_mode(&mut self, buffer: Option<BufferSettings>) {
@@ -96,17 +247,84 @@ impl Terminal {
         self.tty.set_echo(echo);
     }
 
+    pub fn keyboard_flags(&self) -> u32 {
+        self.tty.keyboard_flags()
+    }
+
+    pub fn push_keyboard_flags(&mut self, flags: u32) {
+        self.tty.push_keyboard_flags(flags);
+        self.keyboard_flags_changed();
+    }
+
+    pub fn pop_keyboard_flags(&mut self, n: u32) {
+        self.tty.pop_keyboard_flags(n);
+        self.keyboard_flags_changed();
+    }
+
+    pub fn set_keyboard_flags(&mut self, flags: u32, mode: u32) {
+        self.tty.set_keyboard_flags(flags, mode);
+        self.keyboard_flags_changed();
+    }
+
+    fn keyboard_flags_changed(&mut self) {
+        let flags = self.tty.keyboard_flags();
+        self.events.mode_changed(Mode::KeyboardFlags(flags));
+    }
+
+    pub fn set_modify_other_keys(&mut self, level: u32) {
+        self.tty.set_modify_other_keys(level);
+        self.events.mode_changed(Mode::ModifyOtherKeys(level));
+    }
+
+    pub fn set_keypad_mode(&mut self, app_mode: bool) {
+        self.tty.set_keypad_mode(app_mode);
+        self.events.mode_changed(Mode::KeypadApplication(app_mode));
+    }
+
+    pub fn focus_reporting(&self) -> bool {
+        self.tty.focus_reporting()
+    }
+
+    pub fn set_focus_reporting(&mut self, report: bool) {
+        self.tty.set_focus_reporting(report);
+        self.events.mode_changed(Mode::FocusReporting(report));
+    }
+
+    /// Set the flags which control which keys are reported in the notty input mode.
+    pub fn set_input_flags(&mut self, flags: u32) {
+        self.tty.set_notty_flags(flags);
+    }
+
+    pub fn erase_scrollback(&mut self) {
+        self.active.truncate_scrollback();
+    }
+
     pub fn bell(&mut self) {
-        println!("BELL");
+        self.events.bell();
+    }
+
+    /// Record a problem found in the output of the controlling process.
+    pub fn report(&mut self, problem: Problem, detail: &str) {
+        self.diagnostics.report(problem, detail);
+    }
+
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn diagnostics_mut(&mut self) -> &mut Diagnostics {
+        &mut self.diagnostics
     }
 
     pub fn set_visible_height(&mut self, rows: u32) {
         self.active.set_height(rows);
+        for grid in &mut self.inactive { grid.set_height(rows); }
         self.height = rows;
     }
 
     pub fn set_visible_width(&mut self, cols: u32) {
         self.active.set_width(cols);
+        for grid in &mut self.inactive { grid.set_width(cols); }
         self.width = cols;
     }
 
@@ -124,3 +342,29 @@ impl DerefMut for Terminal {
         &mut self.active
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::{CellData, Movement};
+
+    use super::*;
+
+    #[test]
+    fn with_scrollback() {
+        let mut terminal = Terminal::with_scrollback(5, 3, Scrollback::Rows(4), Vec::new(),
+                                                     NoEvents);
+        for c in "abcdefgh".chars() {
+            terminal.write(CellData::Char(c));
+            terminal.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(terminal.scrollback_height(), 1);
+        terminal.push_buffer(false, true);
+        for c in "abcdefgh".chars() {
+            terminal.write(CellData::Char(c));
+            terminal.move_cursor(Movement::NextLine(1));
+        }
+        assert_eq!(terminal.scrollback_height(), 1);
+    }
+
+}
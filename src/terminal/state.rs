@@ -0,0 +1,172 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+//! The state of a terminal as plain data, which can be serialized with serde so that a session
+//! survives a restart of its frontend, or is attached to a bug report.
+use std::io::Write;
+
+use terminal::{CharGrid, Diagnostics, GridState, Scrollback, StateError, Terminal};
+use terminal::TerminalEvents;
+
+use super::input::Input;
+
+/// The version of the state format written by this build. States of any other version are
+/// rejected when restored.
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TerminalState {
+    pub version: u32,
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub cwd: Option<String>,
+    pub active: GridState,
+    /// The buffers beneath the active one, from the bottom.
+    pub inactive: Vec<GridState>,
+    pub scrollback: Scrollback,
+    pub focused: bool,
+    pub input: InputState,
+}
+
+/// The modes which determine what the terminal sends to the controlling process. Modes are
+/// written in the notty argument encoding.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputState {
+    pub mode: String,
+    pub keyboard_flags: Vec<u32>,
+    /// The keyboard flags stacks of the buffers beneath the active one, from the bottom.
+    pub inactive_keyboard_flags: Vec<Vec<u32>>,
+    pub modify_other_keys: u32,
+    pub keypad: bool,
+    pub notty_flags: u32,
+    pub focus_events: bool,
+    pub echo: Option<String>,
+    pub buffer: Option<String>,
+}
+
+impl Terminal {
+    /// Save the state of this terminal. A synchronized update in progress is not saved.
+    pub fn state(&self) -> Result<TerminalState, StateError> {
+        let inactive: Result<Vec<GridState>, StateError> = self.inactive.iter().map(|grid| {
+            grid.state()
+        }).collect();
+        Ok(TerminalState {
+            version: STATE_VERSION,
+            width: self.width,
+            height: self.height,
+            title: self.title.clone(),
+            cwd: self.cwd.clone(),
+            active: try!(self.active.state()),
+            inactive: try!(inactive),
+            scrollback: self.scrollback,
+            focused: self.focused,
+            input: self.tty.state(),
+        })
+    }
+
+    /// Restore a terminal from a saved state, connected to a new tty and events.
+    pub fn restore<W, E>(state: &TerminalState, tty: W, events: E) -> Result<Terminal, StateError>
+            where W: Write + 'static, E: TerminalEvents + 'static {
+        if state.version != STATE_VERSION { return Err(StateError::Version(state.version)); }
+        if state.input.inactive_keyboard_flags.len() != state.inactive.len() {
+            return Err(StateError::Invalid("wrong number of keyboard flags stacks"));
+        }
+        for grid in Some(&state.active).into_iter().chain(&state.inactive) {
+            if grid.visible_width != state.width || grid.visible_height != state.height {
+                return Err(StateError::Invalid("grid not the size of the terminal"));
+            }
+        }
+        let inactive: Result<Vec<CharGrid>, StateError> = state.inactive.iter().map(|grid| {
+            CharGrid::from_state(grid)
+        }).collect();
+        let mut input = Input::new(tty);
+        try!(input.restore(&state.input));
+        Ok(Terminal {
+            width: state.width,
+            height: state.height,
+            title: state.title.clone(),
+            cwd: state.cwd.clone(),
+            active: try!(CharGrid::from_state(&state.active)),
+            inactive: try!(inactive),
+            scrollback: state.scrollback,
+            focused: state.focused,
+            sync_update: None,
+            tty: input,
+            events: Box::new(events),
+            diagnostics: Diagnostics::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use serde_json;
+
+    use datatypes::{CellData, Coords, Movement, Style};
+    use terminal::{NoEvents, StateError, Terminal};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut terminal = Terminal::new(5, 2, Vec::new(), NoEvents);
+        terminal.set_style(Style::Bold(true));
+        for c in "abcdefgh".chars() {
+            terminal.write(CellData::Char(c));
+            terminal.move_cursor(Movement::NextLine(1));
+        }
+        terminal.add_tooltip(Coords {x:0, y:1}, String::from("tip"));
+        terminal.push_keyboard_flags(1);
+        terminal.push_buffer(false, false);
+        terminal.write(CellData::Char('\u{4e2d}'));
+        terminal.set_title(String::from("title"));
+        terminal.push_keyboard_flags(3);
+        let state = terminal.state().unwrap();
+        let json = serde_json::to_string(&state).unwrap();
+        let state2: TerminalState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, state2);
+        let restored = Terminal::restore(&state2, Vec::new(), NoEvents).unwrap();
+        assert_eq!(restored.state().unwrap(), state);
+        assert_eq!(restored.title(), "title");
+        assert_eq!(restored.keyboard_flags(), 3);
+        assert_eq!(restored.state().unwrap().input.inactive_keyboard_flags, [[1]]);
+        assert_eq!(restored.cursor_position(), Coords {x:2, y:0});
+    }
+
+    #[test]
+    fn invalid_state() {
+        let terminal = Terminal::new(5, 2, Vec::new(), NoEvents);
+        let mut state = terminal.state().unwrap();
+        state.active.cursor.x = 5;
+        match Terminal::restore(&state, Vec::new(), NoEvents) {
+            Err(StateError::Invalid(_)) => (),
+            _                           => panic!("restored a cursor outside of the grid"),
+        }
+        state.active.cursor.x = 0;
+        state.width = 6;
+        match Terminal::restore(&state, Vec::new(), NoEvents) {
+            Err(StateError::Invalid(_)) => (),
+            _                           => panic!("restored a terminal of the wrong size"),
+        }
+        state.version = STATE_VERSION + 1;
+        match Terminal::restore(&state, Vec::new(), NoEvents) {
+            Err(StateError::Version(_)) => (),
+            _                           => panic!("restored an unknown version"),
+        }
+    }
+
+}